@@ -0,0 +1,186 @@
+//! `cargo autodiff [--json] [--header FILE] [PATH]`
+//!
+//! Walks the source files of the crate at `PATH` (the current directory by default),
+//! finds every `#[differentiate_ext(...)]`, also on foreign functions and extern blocks,
+//! and every `declare_derivative!` entry, and prints the primal signature,
+//! the generated derivative declaration, the return struct
+//! and an explanation of the role of each parameter.
+//! With `--header FILE` it instead writes a C header for all of them to `FILE`.
+
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use autodiff_codegen::header::write_header;
+use autodiff_codegen::scan::{scan_dir, scan_file, Found};
+use quote::ToTokens;
+
+const USAGE: &str = "usage: cargo autodiff [--json] [--header FILE] [PATH]";
+
+/// Makes the output of `to_token_stream().to_string()` a bit more readable.
+fn tidy(tokens: impl ToTokens) -> String {
+    tidy_str(&tokens.to_token_stream().to_string())
+}
+
+fn tidy_str(tokens: &str) -> String {
+    let mut s = tokens.to_owned();
+    for (from, to) in [
+        (" : ", ": "),
+        (" :: ", "::"),
+        (" , ", ", "),
+        (" ,", ","),
+        ("& ", "&"),
+        ("* const ", "*const "),
+        ("* mut ", "*mut "),
+        (" (", "("),
+        ("( ", "("),
+        (" )", ")"),
+        (" ; ", "; "),
+        (" ;", ";"),
+        ("[ ", "["),
+        (" ]", "]"),
+        ("< ", "<"),
+        (" <", "<"),
+        (" >", ">"),
+        ("# [", "#["),
+    ] {
+        s = s.replace(from, to);
+    }
+    // Undo the damage for return types, `fn f()->T`.
+    s.replace(")->", ") -> ")
+}
+
+fn ret_struct(found: &Found) -> Option<String> {
+    let ret = found.generated.ret_struct.as_ref()?;
+    let fields: Vec<String> = ret
+        .fields
+        .iter()
+        .map(|f| format!("{}: {}", f.ident.as_ref().unwrap(), tidy(&f.ty)))
+        .collect();
+    Some(format!("struct {} {{ {} }}", ret.ident, fields.join(", ")))
+}
+
+fn print_text(found: &[Found]) {
+    for f in found {
+        let name = &f.generated.declaration.sig.ident;
+        let primal = &f.generated.primal.sig;
+        println!("{}:{}: {name}", f.file.display(), f.line);
+        println!("  primal:     {}", tidy(primal));
+        println!("  derivative: {}", tidy(&f.generated.declaration.sig));
+        if let Some(ret) = ret_struct(f) {
+            println!("  returns:    {ret}");
+        }
+        println!("  parameters:");
+        for p in &f.params {
+            let ty = tidy_str(&p.ty);
+            println!("    {}: {ty} ({}), {}", p.name, p.activity, p.role);
+        }
+        println!("  config:");
+        for line in f.config.to_string().lines() {
+            println!("    {line}");
+        }
+        println!();
+    }
+}
+
+fn json_str(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn print_json(found: &[Found]) {
+    let entries: Vec<String> = found
+        .iter()
+        .map(|f| {
+            let params: Vec<String> = f
+                .params
+                .iter()
+                .map(|p| {
+                    format!(
+                        "{{\"name\":{},\"type\":{},\"activity\":{},\"role\":{}}}",
+                        json_str(&p.name),
+                        json_str(&tidy_str(&p.ty)),
+                        json_str(&p.activity),
+                        json_str(&p.role)
+                    )
+                })
+                .collect();
+            let ret = ret_struct(f).map_or("null".to_owned(), |r| json_str(&r));
+            format!(
+                "{{\"file\":{},\"line\":{},\"name\":{},\"primal\":{},\"derivative\":{},\"return_struct\":{ret},\"parameters\":[{}],\"config\":{}}}",
+                json_str(&f.file.display().to_string()),
+                f.line,
+                json_str(&f.generated.declaration.sig.ident.to_string()),
+                json_str(&tidy(&f.generated.primal.sig)),
+                json_str(&tidy(&f.generated.declaration.sig)),
+                params.join(","),
+                json_str(&f.config.to_string()),
+            )
+        })
+        .collect();
+    println!("[{}]", entries.join(",\n "));
+}
+
+fn main() -> ExitCode {
+    let mut json = false;
+    let mut header: Option<PathBuf> = None;
+    let mut path: Option<PathBuf> = None;
+    // When invoked as `cargo autodiff`, cargo passes `autodiff` as first argument.
+    let mut args = std::env::args().skip(1).skip_while(|a| a == "autodiff");
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--json" => json = true,
+            "--header" if header.is_none() => match args.next() {
+                Some(file) => header = Some(file.into()),
+                None => {
+                    eprintln!("{USAGE}");
+                    return ExitCode::FAILURE;
+                }
+            },
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return ExitCode::SUCCESS;
+            }
+            _ if path.is_none() && !arg.starts_with('-') => path = Some(arg.into()),
+            _ => {
+                eprintln!("{USAGE}");
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    let path = path.unwrap_or_else(|| PathBuf::from("."));
+    let src = path.join("src");
+    let path = match path.is_file() || !src.is_dir() {
+        true => path,
+        false => src,
+    };
+    if let Some(header) = header {
+        if let Err(e) = write_header(&path, &header) {
+            eprintln!("error: {e}");
+            return ExitCode::FAILURE;
+        }
+        return ExitCode::SUCCESS;
+    }
+    let result = match path.is_file() {
+        true => scan_file(&path),
+        false => scan_dir(&path),
+    };
+    match result {
+        Ok(found) if json => print_json(&found),
+        Ok(found) => print_text(&found),
+        Err(e) => {
+            eprintln!("error: {e}");
+            return ExitCode::FAILURE;
+        }
+    }
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,100 @@
+//! Attributes for functions and statics which are used by a primal,
+//! rather than differentiated themselves.
+//!
+//! The backend has to find these items in the module it differentiates,
+//! so we export them under their own name (plus the symbol prefix)
+//! and keep functions from being inlined.
+//! Next to each item we emit a static `__enzyme_<kind>_<name>` with `key=value` lines,
+//! like the metadata of a derivative.
+
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{Attribute, Error, Ident, Item, ItemFn, ItemStatic, Result};
+
+use crate::metadata::Metadata;
+use crate::{export_attr, Options};
+
+/// Rejects attributes which would clash with exporting the item ourselves.
+fn check_attrs(attrs: &[Attribute], kind: &str, conflicting: &[&str]) -> Result<()> {
+    let conflict = attrs
+        .iter()
+        .find(|attr| conflicting.iter().any(|name| attr.path.is_ident(name)));
+    match conflict {
+        Some(attr) => Err(Error::new_spanned(
+            attr,
+            format!(
+                "Items marked as {kind} are exported by autodiff, please remove this attribute!"
+            ),
+        )),
+        None => Ok(()),
+    }
+}
+
+/// The export attribute and the metadata static for an item named `name`, with `kind=true` set.
+fn export(name: &Ident, kind: &str) -> (Attribute, ItemStatic) {
+    // There is no per-item setting, so we use the same default as `differentiate_ext`.
+    let prefix = Options::default().symbol_prefix();
+    let mut meta = Metadata::default();
+    meta.push("name", name);
+    meta.push("symbol", format!("{prefix}{name}"));
+    meta.push(kind, true);
+    let ident = Ident::new(&format!("__enzyme_{kind}_{name}"), name.span());
+    (
+        export_attr(name, &prefix),
+        meta.to_static_named(ident, &prefix),
+    )
+}
+
+/// Exports a function for the backend and emits its metadata.
+/// It's never inlined, so the backend sees its calls.
+fn annotate_fn(item: &ItemFn, kind: &str) -> Result<TokenStream> {
+    let sig = &item.sig;
+    if sig.generics.type_params().next().is_some() || sig.generics.const_params().next().is_some() {
+        return Err(Error::new_spanned(
+            &sig.generics,
+            format!("Generic functions can't be marked as {kind}, since the backend couldn't find them by name!"),
+        ));
+    }
+    check_attrs(&item.attrs, kind, &["inline", "no_mangle", "export_name"])?;
+    let (export, meta_static) = export(&sig.ident, kind);
+    let ItemFn {
+        attrs,
+        vis,
+        sig,
+        block,
+    } = item;
+    Ok(quote! {
+        #(#attrs)*
+        #[inline(never)]
+        #export
+        #vis #sig #block
+        #meta_static
+    })
+}
+
+/// `#[checkpoint]`: calls of this function are recomputed in the reverse pass,
+/// instead of caching the intermediate values they compute.
+pub fn checkpoint(item: &ItemFn) -> Result<TokenStream> {
+    annotate_fn(item, "checkpoint")
+}
+
+/// `#[inactive]`: functions and statics which never contribute to a derivative,
+/// like logging, random number generators or counters. The backend treats them as constant.
+pub fn inactive(item: &Item) -> Result<TokenStream> {
+    match item {
+        Item::Fn(item) => annotate_fn(item, "inactive"),
+        Item::Static(item) => {
+            check_attrs(&item.attrs, "inactive", &["no_mangle", "export_name"])?;
+            let (export, meta_static) = export(&item.ident, "inactive");
+            Ok(quote! {
+                #export
+                #item
+                #meta_static
+            })
+        }
+        item => Err(Error::new_spanned(
+            item,
+            "Only functions and statics can be marked as inactive!",
+        )),
+    }
+}
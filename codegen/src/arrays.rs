@@ -0,0 +1,161 @@
+//! Arrays passed or returned by value.
+//!
+//! C has no array values, so `extern "C"` functions can't take or return `[T; N]` safely.
+//! At the ABI boundary we therefore wrap such arrays in a `#[repr(C)]` tuple struct,
+//! which has the same layout as the array but is passed like any other C struct.
+//! The primal shim and the declaration use the struct, while users keep passing plain arrays
+//! through a wrapper around the declaration.
+//! Arrays inside return structs are already fine, so gradient fields of `Active` arrays stay arrays.
+//!
+//! Lengths have to be known when expanding, as literals or const items. Functions generic over
+//! `const N: usize` are rejected, since a generic shim can't be exported for the backend.
+
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{
+    parse_quote, Error, FnArg, Ident, ItemFn, ItemStruct, Result, ReturnType, Signature, Type,
+};
+
+use crate::helper::{dedup_param_names, hide_declaration, Hidden};
+use crate::Generated;
+
+/// Name of the struct wrapping the arrays of one primal, `__enzyme_array_<fn>`.
+pub(crate) fn struct_name(primal: &Ident) -> Ident {
+    Ident::new(&format!("__enzyme_array_{primal}"), primal.span())
+}
+
+/// The struct wrapping arrays at the ABI boundary, generated next to the primal shim.
+pub(crate) fn array_struct(primal: &Ident) -> ItemStruct {
+    let name = struct_name(primal);
+    parse_quote! {
+        #[doc(hidden)]
+        #[allow(non_camel_case_types)]
+        #[repr(C)]
+        #[derive(Clone, Copy)]
+        struct #name<T, const N: usize>([T; N]);
+    }
+}
+
+fn array_elem(ty: &Type) -> Option<(&Type, &syn::Expr)> {
+    match ty {
+        Type::Array(array) => Some((&array.elem, &array.len)),
+        Type::Paren(paren) => array_elem(&paren.elem),
+        Type::Group(group) => array_elem(&group.elem),
+        _ => None,
+    }
+}
+
+/// The type used at the ABI boundary, if `ty` is an array which needs to be wrapped.
+pub(crate) fn abi_type(ty: &Type, primal: &Ident) -> Option<Type> {
+    let (elem, len) = array_elem(ty)?;
+    let name = struct_name(primal);
+    Some(parse_quote! { #name<#elem, { #len }> })
+}
+
+fn param_type(param: &FnArg) -> Option<&Type> {
+    match param {
+        FnArg::Typed(pat_ty) => Some(&pat_ty.ty),
+        FnArg::Receiver(_) => None,
+    }
+}
+
+/// Does this signature pass or return arrays by value?
+pub(crate) fn has_arrays(sig: &Signature) -> bool {
+    let ret = match &sig.output {
+        ReturnType::Type(_, ty) => array_elem(ty).is_some(),
+        ReturnType::Default => false,
+    };
+    ret || sig
+        .inputs
+        .iter()
+        .filter_map(param_type)
+        .any(|ty| array_elem(ty).is_some())
+}
+
+/// Replaces arrays in the parameters and return type of `sig` by their ABI type.
+/// Returns the arguments for calling a function with the old signature from one with the new one,
+/// or the other way around if `wrap` is set, and whether the return value needs to be converted.
+fn to_abi(sig: &mut Signature, primal: &Ident, wrap: bool) -> (Vec<TokenStream>, bool) {
+    let name = struct_name(primal);
+    let mut args = vec![];
+    for (i, param) in sig.inputs.iter_mut().enumerate() {
+        let pat_ty = match param {
+            FnArg::Typed(pat_ty) => pat_ty,
+            FnArg::Receiver(_) => unreachable!("receivers are rejected earlier"),
+        };
+        let arg = match &*pat_ty.pat {
+            syn::Pat::Ident(pat_ident) => pat_ident.ident.clone(),
+            _ => Ident::new(&format!("arg{i}"), proc_macro2::Span::mixed_site()),
+        };
+        match abi_type(&pat_ty.ty, primal) {
+            Some(abi) => {
+                *pat_ty.ty = abi;
+                args.push(match wrap {
+                    true => quote! { #name(#arg) },
+                    false => quote! { #arg.0 },
+                });
+            }
+            None => args.push(quote! { #arg }),
+        }
+    }
+    let mut ret_is_array = false;
+    if let ReturnType::Type(_, ty) = &mut sig.output {
+        if let Some(abi) = abi_type(ty, primal) {
+            **ty = abi;
+            ret_is_array = true;
+        }
+    }
+    (args, ret_is_array)
+}
+
+/// Wraps arrays in the signature of the primal shim.
+/// Returns the body of the shim, `call` builds the call of the primal from its arguments.
+pub(crate) fn wrap_shim(
+    sig: &mut Signature,
+    primal: &Ident,
+    call: impl Fn(&[TokenStream]) -> TokenStream,
+) -> TokenStream {
+    let (args, ret_is_array) = to_abi(sig, primal, false);
+    let call = call(&args);
+    let name = struct_name(primal);
+    match ret_is_array {
+        true => quote! { #name(#call) },
+        false => call,
+    }
+}
+
+/// Hides a declaration which passes arrays by value behind a wrapper,
+/// which converts them to the `#[repr(C)]` struct used at the ABI boundary.
+pub(crate) fn wrap_declaration(primal: &Ident, generated: &mut Generated) -> Result<()> {
+    if !has_arrays(&generated.declaration.sig) {
+        return Ok(());
+    }
+    if generated.wrapper.is_some() {
+        return Err(Error::new(
+            generated.declaration.sig.ident.span(),
+            "Arrays passed by value can't be combined with `layout = array` or const widths yet, please pass them by reference!",
+        ));
+    }
+    dedup_param_names(&mut generated.declaration.sig);
+    let Hidden {
+        raw,
+        docs,
+        vis,
+        sig,
+    } = hide_declaration(&mut generated.declaration);
+    let (args, ret_is_array) = to_abi(&mut generated.declaration.sig, primal, true);
+    let call = quote! { #raw(#(#args),*) };
+    let body = match ret_is_array {
+        true => quote! { #call.0 },
+        false => call,
+    };
+    let wrapper: ItemFn = parse_quote! {
+        #(#docs)*
+        #[inline]
+        #vis #sig {
+            unsafe { #body }
+        }
+    };
+    generated.wrapper = Some(wrapper);
+    Ok(())
+}
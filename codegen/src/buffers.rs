@@ -0,0 +1,363 @@
+//! Containers passed by reference, like `&Vec<f64>` or `&Box<[f64]>`.
+//!
+//! Such containers can't cross the C ABI themselves, so at the boundary we lower each of them
+//! to a pointer to its elements and a length, through the `autodiff_runtime::AdBuffer` trait.
+//! `Vec<T>` and `Box<[T]>` are recognized by their name,
+//! other types implementing `AdBuffer` have to be listed in the `buffer = [..]` option.
+//!
+//! Slices like `&[f64]` and `&mut [f64]` are lowered the same way, without the trait.
+//!
+//! The primal only gets shared references to containers. Through `&mut Vec<T>` it could grow
+//! or replace the container, which would free memory of the caller, since the view doesn't know
+//! the real capacity. Primals which modify elements take `&mut [T]` instead.
+//!
+//! The primal shim takes `x: *const T, x_len: usize` and rebuilds a view of the container,
+//! the declaration takes the pointers of the primal and all shadows, followed by the length.
+//! A wrapper around the declaration takes the containers again and checks that the shadows
+//! have the same length as the primal. A `Vec` can only be rebuilt if its capacity is its length,
+//! which the wrapper checks as well.
+
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{
+    parse_quote, Error, FnArg, GenericArgument, Ident, ItemFn, Pat, Path, PathArguments, Result,
+    Signature, Type,
+};
+
+use crate::helper::{dedup_param_names, hide_declaration, Hidden};
+use crate::modes::forward::FwdInfo;
+use crate::{arrays, DiffConfig, Generated};
+
+/// A container parameter of the primal.
+pub(crate) struct Buffer {
+    name: Ident,
+    /// The container type, behind the reference.
+    ty: Type,
+    /// The type of the elements, which the pointers point to.
+    elem: Type,
+    mutable: bool,
+    /// Slices are rebuilt with `core::slice` instead of `AdBuffer`.
+    slice: bool,
+}
+
+impl Buffer {
+    fn len_ident(&self) -> Ident {
+        Ident::new(&format!("{}_len", self.name), self.name.span())
+    }
+
+    /// The pointer to the elements of `arg` and their number, as used by the wrapper.
+    fn raw_parts(&self, arg: &Ident, mutable: bool) -> (TokenStream, TokenStream) {
+        let ty = &self.ty;
+        match (self.slice, mutable) {
+            (true, true) => (quote! { #arg.as_mut_ptr() }, quote! { #arg.len() }),
+            (true, false) => (quote! { #arg.as_ptr() }, quote! { #arg.len() }),
+            (false, true) => (
+                quote! { <#ty as ::autodiff_runtime::AdBuffer>::as_raw_mut(#arg).0 },
+                quote! { <#ty as ::autodiff_runtime::AdBuffer>::as_raw(&*#arg).1 },
+            ),
+            (false, false) => (
+                quote! { <#ty as ::autodiff_runtime::AdBuffer>::as_raw(#arg).0 },
+                quote! { <#ty as ::autodiff_runtime::AdBuffer>::as_raw(&*#arg).1 },
+            ),
+        }
+    }
+
+    fn is_vec(&self) -> bool {
+        match &self.ty {
+            Type::Path(p) => {
+                !self.slice && p.path.segments.last().is_some_and(|seg| seg.ident == "Vec")
+            }
+            _ => false,
+        }
+    }
+
+    /// The pointer to the elements of a parameter with the same mutability as `ty`.
+    /// Shadows can have other elements than the primal, e.g. atomics in a parallel context.
+    fn pointer(&self, ty: &Type) -> Type {
+        let elem = match ty {
+            Type::Reference(r) => match &*r.elem {
+                Type::Slice(slice) => &*slice.elem,
+                _ => &self.elem,
+            },
+            _ => &self.elem,
+        };
+        match ty {
+            Type::Reference(r) if r.mutability.is_some() => parse_quote! { *mut #elem },
+            _ => parse_quote! { *const #elem },
+        }
+    }
+}
+
+/// The single generic argument of the last path segment, e.g. `T` for `Vec<T>`.
+fn generic_arg(path: &Path) -> Option<&Type> {
+    match &path.segments.last()?.arguments {
+        PathArguments::AngleBracketed(args) if args.args.len() == 1 => match &args.args[0] {
+            GenericArgument::Type(ty) => Some(ty),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// The element type of a container, if `ty` is `Vec<T>`, `Box<[T]>` or listed in `buffers`.
+pub(crate) fn buffer_elem(ty: &Type, buffers: &[Path]) -> Option<Type> {
+    let path = match ty {
+        Type::Path(p) if p.qself.is_none() => &p.path,
+        Type::Paren(p) => return buffer_elem(&p.elem, buffers),
+        Type::Group(g) => return buffer_elem(&g.elem, buffers),
+        _ => return None,
+    };
+    if buffers.contains(path) {
+        return Some(parse_quote! { <#path as ::autodiff_runtime::AdBuffer>::Elem });
+    }
+    let last = &path.segments.last()?.ident;
+    match (last.to_string().as_str(), generic_arg(path)?) {
+        ("Vec", elem) => Some(elem.clone()),
+        ("Box", Type::Slice(slice)) => Some((*slice.elem).clone()),
+        _ => None,
+    }
+}
+
+/// Is `ty` a reference to a container or a slice?
+fn buffer_ref(ty: &Type, buffers: &[Path]) -> Option<(Type, Type, bool, bool)> {
+    match ty {
+        Type::Reference(r) => {
+            let mutable = r.mutability.is_some();
+            if let Type::Slice(slice) = &*r.elem {
+                return Some(((*r.elem).clone(), (*slice.elem).clone(), mutable, true));
+            }
+            let elem = buffer_elem(&r.elem, buffers)?;
+            Some(((*r.elem).clone(), elem, mutable, false))
+        }
+        Type::Paren(p) => buffer_ref(&p.elem, buffers),
+        Type::Group(g) => buffer_ref(&g.elem, buffers),
+        _ => None,
+    }
+}
+
+/// The container parameters of `sig`, in order.
+pub(crate) fn buffer_params(sig: &Signature, buffers: &[Path]) -> Vec<Buffer> {
+    sig.inputs
+        .iter()
+        .filter_map(|param| match param {
+            FnArg::Typed(pat_ty) => {
+                let (ty, elem, mutable, slice) = buffer_ref(&pat_ty.ty, buffers)?;
+                let name = match &*pat_ty.pat {
+                    Pat::Ident(pat_ident) => pat_ident.ident.clone(),
+                    _ => return None,
+                };
+                Some(Buffer {
+                    name,
+                    ty,
+                    elem,
+                    mutable,
+                    slice,
+                })
+            }
+            FnArg::Receiver(_) => None,
+        })
+        .collect()
+}
+
+/// Rejects containers which the primal takes by mutable reference, see the module documentation.
+pub(crate) fn reject_mutable(buffers: &[Buffer]) -> Result<()> {
+    match buffers
+        .iter()
+        .find(|buffer| buffer.mutable && !buffer.slice)
+    {
+        Some(Buffer { name, ty, .. }) => {
+            let ty = quote! { #ty }.to_string();
+            Err(Error::new(
+                name.span(),
+                format!("`{name}` can't be passed as `&mut {ty}`, since the primal could grow or replace the container of the caller! Please take a mutable slice of its elements instead."),
+            ))
+        }
+        None => Ok(()),
+    }
+}
+
+/// Lowers the containers in the signature of the primal shim to a pointer and a length.
+/// Returns the statements rebuilding the containers and slices from them,
+/// after which the shim can call the primal with `&*x` or the slice `x` instead of `x`.
+pub(crate) fn lower_shim(sig: &mut Signature, buffers: &[Buffer]) -> TokenStream {
+    let mut views = vec![];
+    let inputs = std::mem::take(&mut sig.inputs);
+    for mut param in inputs {
+        let buffer = match &param {
+            FnArg::Typed(pat_ty) => match &*pat_ty.pat {
+                Pat::Ident(pat_ident) => buffers.iter().find(|b| b.name == pat_ident.ident),
+                _ => None,
+            },
+            FnArg::Receiver(_) => None,
+        };
+        let buffer = match buffer {
+            Some(buffer) => buffer,
+            None => {
+                sig.inputs.push(param);
+                continue;
+            }
+        };
+        let Buffer {
+            name,
+            ty,
+            elem,
+            mutable,
+            slice,
+        } = buffer;
+        let len = buffer.len_ident();
+        if let FnArg::Typed(pat_ty) = &mut param {
+            *pat_ty.ty = buffer.pointer(&pat_ty.ty);
+        }
+        sig.inputs.push(param);
+        sig.inputs.push(parse_quote! { #len: usize });
+        views.push(match (slice, mutable) {
+            (true, true) => quote! {
+                let #name = unsafe { ::core::slice::from_raw_parts_mut(#name, #len) };
+            },
+            (true, false) => quote! {
+                let #name = unsafe { ::core::slice::from_raw_parts(#name, #len) };
+            },
+            _ => quote! {
+                let #name = unsafe {
+                    <#ty as ::autodiff_runtime::AdBuffer>::view(#name as *mut #elem, #len)
+                };
+            },
+        });
+    }
+    quote! { #(#views)* }
+}
+
+/// The argument passed to the primal for the i-th parameter of the shim,
+/// borrowing from the rebuilt container if it's one.
+pub(crate) fn shim_arg(arg: &TokenStream, name: Option<&Ident>, buffers: &[Buffer]) -> TokenStream {
+    match name.and_then(|name| buffers.iter().find(|b| b.name == *name)) {
+        Some(Buffer { slice: true, .. }) => arg.clone(),
+        Some(_) => quote! { &*#arg },
+        None => arg.clone(),
+    }
+}
+
+/// Lowers the containers of the declaration to pointers and lengths,
+/// and hides it behind a wrapper which takes the containers again.
+pub(crate) fn wrap_declaration(
+    config: &DiffConfig,
+    primal: &Signature,
+    generated: &mut Generated,
+) -> Result<()> {
+    let buffers = buffer_params(primal, &config.options().buffer);
+    if buffers.is_empty() {
+        return Ok(());
+    }
+    let runtime_width = matches!(
+        config,
+        DiffConfig::Fwd(FwdInfo {
+            runtime_width: Some(_),
+            ..
+        })
+    );
+    if generated.wrapper.is_some() || runtime_width || arrays::has_arrays(primal) {
+        return Err(Error::new(
+            generated.declaration.sig.ident.span(),
+            "Containers like Vec can't be combined with arrays passed by value, `layout = array` or runtime widths yet!",
+        ));
+    }
+
+    // The parameters of each container: its primal and the shadows following it.
+    // Reverse mode shadows share the name of their primal, forward mode ones are `d_<name>_<i>`.
+    let mut groups: Vec<(&Buffer, Vec<usize>)> = vec![];
+    for (i, param) in generated.declaration.sig.inputs.iter().enumerate() {
+        let name = match param {
+            FnArg::Typed(pat_ty) => match &*pat_ty.pat {
+                Pat::Ident(pat_ident) => pat_ident.ident.to_string(),
+                _ => continue,
+            },
+            FnArg::Receiver(_) => continue,
+        };
+        if let Some((buffer, params)) = groups.last_mut() {
+            let shadow = name
+                .strip_prefix(&format!("d_{}_", buffer.name))
+                .is_some_and(|i| i.parse::<u32>().is_ok());
+            if params.last() == Some(&(i - 1)) && (buffer.name == name || shadow) {
+                params.push(i);
+                continue;
+            }
+        }
+        if let Some(buffer) = buffers.iter().find(|b| b.name == name) {
+            groups.push((buffer, vec![i]));
+        }
+    }
+
+    dedup_param_names(&mut generated.declaration.sig);
+    let Hidden {
+        raw,
+        docs,
+        vis,
+        sig,
+    } = hide_declaration(&mut generated.declaration);
+
+    let mut lowered = syn::punctuated::Punctuated::new();
+    let mut checks = vec![];
+    let mut args = vec![];
+    for (i, param) in generated.declaration.sig.inputs.iter().enumerate() {
+        let arg = match param {
+            FnArg::Typed(pat_ty) => match &*pat_ty.pat {
+                Pat::Ident(pat_ident) => pat_ident.ident.clone(),
+                _ => unreachable!("declarations only have plain parameter names"),
+            },
+            FnArg::Receiver(_) => unreachable!("receivers are rejected earlier"),
+        };
+        let group = groups.iter().find(|(_, params)| params.contains(&i));
+        let (buffer, params) = match group {
+            Some(group) => group,
+            None => {
+                lowered.push(param.clone());
+                args.push(quote! { #arg });
+                continue;
+            }
+        };
+        let mut param = param.clone();
+        let mutable = match &mut param {
+            FnArg::Typed(pat_ty) => {
+                let mutable = matches!(&*pat_ty.ty, Type::Reference(r) if r.mutability.is_some());
+                *pat_ty.ty = buffer.pointer(&pat_ty.ty);
+                mutable
+            }
+            FnArg::Receiver(_) => unreachable!("receivers are rejected earlier"),
+        };
+        lowered.push(param);
+        let len = buffer.len_ident();
+        let (ptr, arg_len) = buffer.raw_parts(&arg, mutable);
+        args.push(ptr);
+        if params[0] == i {
+            checks.push(quote! { let #len = #arg_len; });
+            // The shim rebuilds the primal `Vec` from the length, which has to be its capacity.
+            if buffer.is_vec() {
+                let msg = format!("`{arg}` needs a capacity of exactly its length, please call `shrink_to_fit` first");
+                checks.push(quote! {
+                    assert_eq!(#arg.capacity(), #len, #msg);
+                });
+            }
+        } else {
+            let msg = format!("`{arg}` needs as many elements as `{}`", buffer.name);
+            checks.push(quote! {
+                assert_eq!(#arg_len, #len, #msg);
+            });
+        }
+        if params.last() == Some(&i) {
+            lowered.push(parse_quote! { #len: usize });
+            args.push(quote! { #len });
+        }
+    }
+    generated.declaration.sig.inputs = lowered;
+
+    let wrapper: ItemFn = parse_quote! {
+        #(#docs)*
+        #[inline]
+        #vis #sig {
+            #(#checks)*
+            unsafe { #raw(#(#args),*) }
+        }
+    };
+    generated.wrapper = Some(wrapper);
+    Ok(())
+}
@@ -0,0 +1,207 @@
+//! Function pointer parameters, like `rhs: extern "C" fn(f64, *const f64, *mut f64)`.
+//!
+//! A `Duplicated` callback gets the derivative of the callee as shadow, which has to be declared
+//! by another `differentiate_ext` and named in the `callback(rhs = d_rhs)` option.
+//! Declarations can't name the type of another function, so next to each declaration we emit
+//! the aliases `__enzyme_fn_<name>` for its own type and `__enzyme_primal_fn_<name>` for the
+//! type of its primal. The shadow is typed by the former, and we check that the latter is the
+//! type of the callback parameter, so users can't pass the derivative of an unrelated function.
+
+use quote::{quote, quote_spanned};
+use syn::spanned::Spanned;
+use syn::{parse_quote, Error, FnArg, GenericParam, Ident, Pat, Path, Result, Signature, Type};
+
+use crate::modes::forward::FwdInfo;
+use crate::{DiffConfig, Generated};
+
+/// Name of the alias for the function pointer type of the derivative `name`.
+pub fn fn_type_name(name: &Ident) -> Ident {
+    Ident::new(&format!("__enzyme_fn_{name}"), name.span())
+}
+
+/// Name of the alias for the function pointer type of the primal of the derivative `name`.
+pub fn primal_type_name(name: &Ident) -> Ident {
+    Ident::new(&format!("__enzyme_primal_fn_{name}"), name.span())
+}
+
+/// `path` with its last segment replaced by `rename(last)`, e.g. `a::__enzyme_fn_d_f` for `a::d_f`.
+fn sibling_path(path: &Path, rename: fn(&Ident) -> Ident) -> Path {
+    let mut path = path.clone();
+    let last = path.segments.last_mut().unwrap();
+    last.ident = rename(&last.ident);
+    path
+}
+
+fn is_fn_pointer(ty: &Type) -> bool {
+    match ty {
+        Type::BareFn(_) => true,
+        Type::Paren(p) => is_fn_pointer(&p.elem),
+        Type::Group(g) => is_fn_pointer(&g.elem),
+        _ => false,
+    }
+}
+
+fn param_name(param: &FnArg) -> Option<&Ident> {
+    match param {
+        FnArg::Typed(pat_ty) => match &*pat_ty.pat {
+            Pat::Ident(pat_ident) => Some(&pat_ident.ident),
+            _ => None,
+        },
+        FnArg::Receiver(_) => None,
+    }
+}
+
+/// The type of a function with signature `sig` as function pointer.
+fn pointer_type(sig: &Signature) -> Type {
+    let unsafety = &sig.unsafety;
+    let abi = &sig.abi;
+    let lifetimes: Vec<_> = sig
+        .generics
+        .params
+        .iter()
+        .filter_map(|param| match param {
+            GenericParam::Lifetime(lt) => Some(&lt.lifetime),
+            _ => None,
+        })
+        .collect();
+    let binder = match lifetimes.is_empty() {
+        true => quote! {},
+        false => quote! { for<#(#lifetimes),*> },
+    };
+    let tys = sig.inputs.iter().map(|param| match param {
+        FnArg::Typed(pat_ty) => &*pat_ty.ty,
+        FnArg::Receiver(_) => unreachable!("receivers are rejected earlier"),
+    });
+    let output = &sig.output;
+    parse_quote! { #binder #unsafety #abi fn(#(#tys),*) #output }
+}
+
+/// Types the shadows of callbacks in the declaration and checks them against the `callback` option.
+/// Also emits the aliases through which this derivative can be the shadow of other callbacks.
+pub(crate) fn apply(
+    config: &DiffConfig,
+    primal: &Signature,
+    generated: &mut Generated,
+) -> Result<()> {
+    let callbacks = &config.options().callback;
+    let fn_params: Vec<(&Ident, &Type)> = primal
+        .inputs
+        .iter()
+        .filter_map(|param| match param {
+            FnArg::Typed(pat_ty) if is_fn_pointer(&pat_ty.ty) => {
+                Some((param_name(param)?, &*pat_ty.ty))
+            }
+            _ => None,
+        })
+        .collect();
+    for (name, _) in callbacks {
+        if !fn_params.iter().any(|(param, _)| *param == name) {
+            return Err(Error::new(
+                name.span(),
+                format!(
+                    "`{name}` is listed in `callback`, but isn't a function pointer parameter!"
+                ),
+            ));
+        }
+    }
+
+    let runtime_width = matches!(
+        config,
+        DiffConfig::Fwd(FwdInfo {
+            runtime_width: Some(_),
+            ..
+        })
+    );
+    let mut duplicated = vec![];
+    let mut seen = vec![];
+    for param in generated.declaration.sig.inputs.iter_mut() {
+        let name = match param_name(param) {
+            Some(name) => name.to_string(),
+            None => continue,
+        };
+        // Reverse mode shadows share the name of their primal, forward mode ones are `d_<name>_<i>`.
+        let primal_name = fn_params.iter().map(|(param, _)| *param).find(|param| {
+            **param == name && seen.contains(&name)
+                || name
+                    .strip_prefix(&format!("d_{param}_"))
+                    .is_some_and(|i| i.parse::<u32>().is_ok())
+        });
+        seen.push(name);
+        let primal_name = match primal_name {
+            Some(primal_name) => primal_name,
+            None => continue,
+        };
+        let path = match callbacks.iter().find(|(param, _)| param == primal_name) {
+            Some((_, path)) => path,
+            None => {
+                return Err(Error::new(
+                    primal_name.span(),
+                    format!("The shadow of the function pointer `{primal_name}` is the derivative of the callee, please name it with `callback({primal_name} = d_callee)`!"),
+                ))
+            }
+        };
+        if generated.wrapper.is_some() || runtime_width {
+            return Err(Error::new(
+                primal_name.span(),
+                "Duplicated function pointers can't be combined with wrappers around the derivative, like for arrays, containers or runtime widths, yet!",
+            ));
+        }
+        if let FnArg::Typed(pat_ty) = param {
+            let alias = sibling_path(path, fn_type_name);
+            *pat_ty.ty = parse_quote! { #alias };
+        }
+        if !duplicated.contains(&primal_name) {
+            duplicated.push(primal_name);
+        }
+    }
+
+    for (name, path) in callbacks {
+        if !duplicated.contains(&name) {
+            return Err(Error::new(
+                name.span(),
+                format!("`{name}` is listed in `callback`, but isn't Duplicated!"),
+            ));
+        }
+        let ty = fn_params
+            .iter()
+            .find(|(param, _)| *param == name)
+            .unwrap()
+            .1;
+        let primal_alias = sibling_path(path, primal_type_name);
+        // Fails to compile if the primal of the derivative doesn't have the type of the callback.
+        let check = quote_spanned! { path.span() => |f| f };
+        generated.callbacks.push(parse_quote! {
+            const _: fn(#primal_alias) -> #ty = #check;
+        });
+    }
+
+    // Wrappers are plain Rust functions, which the backend can't call as shadow.
+    let has_generics = primal
+        .generics
+        .params
+        .iter()
+        .any(|param| !matches!(param, GenericParam::Lifetime(_)));
+    if generated.wrapper.is_none() && !has_generics {
+        let decl = &generated.declaration;
+        let name = &decl.sig.ident;
+        let vis = &decl.vis;
+        let mut sig = decl.sig.clone();
+        sig.unsafety = Some(Default::default());
+        sig.abi = Some(parse_quote! { extern "C" });
+        let fn_ty = pointer_type(&sig);
+        let primal_ty = pointer_type(primal);
+        let fn_alias = fn_type_name(name);
+        let primal_alias = primal_type_name(name);
+        generated.callbacks.push(parse_quote! {
+            #[doc(hidden)]
+            #[allow(non_camel_case_types)]
+            #vis type #fn_alias = #fn_ty;
+        });
+        generated.callbacks.push(parse_quote! {
+            #[doc(hidden)]
+            #[allow(non_camel_case_types)]
+            #vis type #primal_alias = #primal_ty;
+        });
+    }
+    Ok(())
+}
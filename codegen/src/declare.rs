@@ -0,0 +1,142 @@
+//! Derivatives of functions defined elsewhere, `declare_derivative!`.
+//!
+//! Functions in other crates or vendored code can't get an attribute, so users repeat their
+//! signature instead: `declare_derivative!(d_f = other_crate::f(x: f64) -> f64, Reverse, ...)`.
+//! We then generate exactly what `differentiate_ext` would generate on `f`,
+//! except for `f` itself. The shim calls the given path, which is also mangled into its name,
+//! e.g. `__enzyme_primal_other_crate__f`, so functions of the same name don't share a symbol.
+//!
+//! Multiple derivatives of the same function can be declared in one invocation,
+//! separated by `;`. They are handled like stacked attributes, so they share one shim.
+
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
+use syn::spanned::Spanned;
+use syn::{
+    parenthesized, Error, FnArg, Ident, ItemFn, Path, Result, ReturnType, Token, Visibility,
+};
+
+use crate::{generate_for, DiffConfig, Generated};
+
+/// One `vis d_f = unsafe path::f(params) -> ret, <mode and activities>` entry.
+pub struct Declaration {
+    vis: Visibility,
+    unsafety: Option<Token![unsafe]>,
+    path: Path,
+    inputs: Punctuated<FnArg, Token![,]>,
+    output: ReturnType,
+    /// The arguments `differentiate_ext` would get, starting with the derivative name.
+    config: TokenStream,
+}
+
+impl Declaration {
+    /// The primal as it would be defined, with an empty body and `attrs` on it.
+    fn primal(&self, attrs: &[&TokenStream]) -> ItemFn {
+        let Declaration {
+            vis,
+            unsafety,
+            path,
+            inputs,
+            output,
+            ..
+        } = self;
+        let name = &path.segments.last().unwrap().ident;
+        syn::parse_quote! {
+            #(#[differentiate_ext(#attrs)])*
+            #vis #unsafety fn #name(#inputs) #output {}
+        }
+    }
+
+    fn signature(&self) -> String {
+        let Declaration {
+            unsafety,
+            inputs,
+            output,
+            ..
+        } = self;
+        quote! { #unsafety (#inputs) #output }.to_string()
+    }
+}
+
+impl Parse for Declaration {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let vis: Visibility = input.parse()?;
+        let name: Ident = input.parse()?;
+        let _: Token![=] = input.parse()?;
+        let unsafety = input.parse()?;
+        let path = Path::parse_mod_style(input)?;
+        let content;
+        parenthesized!(content in input);
+        let inputs = content.parse_terminated(FnArg::parse)?;
+        let output = input.parse()?;
+        let _: Token![,] = input.parse()?;
+        // The rest up to the next `;` is passed on as is, `;` can't occur in it outside of groups.
+        let mut config = quote! { #name, };
+        while !input.is_empty() && !input.peek(Token![;]) {
+            let tt: proc_macro2::TokenTree = input.parse()?;
+            config.extend([tt]);
+        }
+        // Check it early, so errors point to the right entry.
+        syn::parse2::<DiffConfig>(config.clone())?;
+        Ok(Declaration {
+            vis,
+            unsafety,
+            path,
+            inputs,
+            output,
+            config,
+        })
+    }
+}
+
+/// The `;` separated entries of one `declare_derivative!` invocation.
+pub struct Declarations(pub Vec<Declaration>);
+
+impl Parse for Declarations {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let entries = Punctuated::<Declaration, Token![;]>::parse_terminated(input)?;
+        Ok(Declarations(entries.into_iter().collect()))
+    }
+}
+
+/// Generates the derivatives of all entries, without the primals.
+pub fn declare(declarations: &Declarations) -> Result<TokenStream> {
+    let mut out = TokenStream::new();
+    for (_, _, generated) in derivatives(declarations)? {
+        out.extend(generated.derivative_tokens());
+    }
+    Ok(out)
+}
+
+/// The derivatives of all entries, each with the span of its path and its resolved config.
+pub(crate) fn derivatives(
+    declarations: &Declarations,
+) -> Result<Vec<(Span, DiffConfig, Generated)>> {
+    let entries = &declarations.0;
+    let mut derivatives = vec![];
+    for (i, entry) in entries.iter().enumerate() {
+        // Like stacked attributes, each entry sees the later ones for the same function.
+        let later: Vec<&Declaration> = entries[i + 1..]
+            .iter()
+            .filter(|other| other.path == entry.path)
+            .collect();
+        if let Some(other) = later
+            .iter()
+            .find(|other| other.signature() != entry.signature())
+        {
+            return Err(Error::new_spanned(
+                &other.path,
+                "All derivatives of the same function need the same signature!",
+            ));
+        }
+        let attrs: Vec<&TokenStream> = later.iter().map(|other| &other.config).collect();
+        let primal = entry.primal(&attrs);
+        let mut config: DiffConfig = syn::parse2(entry.config.clone())?;
+        let generated = generate_for(config.clone(), &primal, &entry.path)?;
+        config.resolve_name(&primal.sig.ident)?;
+        derivatives.push((entry.path.span(), config, generated));
+    }
+    Ok(derivatives)
+}
@@ -0,0 +1,142 @@
+//! `#[derive(Differentiable)]` for structs of parameters.
+//!
+//! For a `#[repr(C)]` struct `Model` we generate a `ModelTangent` struct with the same fields,
+//! each replaced by its tangent type, and implement `autodiff_runtime::Differentiable` and
+//! `autodiff_runtime::Tangent` for them.
+//! Since every field of the tangent has the layout of the primal field,
+//! a `ModelTangent` can be used as the shadow of a `Model`,
+//! see the `tangent` option of `differentiate_ext`.
+
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::{Data, DeriveInput, Error, Fields, Ident, Index, Member, Meta, NestedMeta, Result};
+
+/// Name of the tangent struct, `<Name>Tangent`.
+pub fn tangent_name(name: &Ident) -> Ident {
+    format_ident!("{name}Tangent")
+}
+
+pub(crate) fn is_repr_c(input: &DeriveInput) -> bool {
+    input.attrs.iter().any(|attr| match attr.parse_meta() {
+        Ok(Meta::List(list)) if list.path.is_ident("repr") => list.nested.iter().any(
+            |nested| matches!(nested, NestedMeta::Meta(Meta::Path(path)) if path.is_ident("C")),
+        ),
+        _ => false,
+    })
+}
+
+/// Generates the tangent struct and the trait implementations for a `#[derive(Differentiable)]`.
+pub fn derive(input: &DeriveInput) -> Result<TokenStream> {
+    let name = &input.ident;
+    let fields = match &input.data {
+        Data::Struct(data) => &data.fields,
+        _ => {
+            return Err(Error::new(
+                name.span(),
+                "Differentiable can only be derived for structs!",
+            ))
+        }
+    };
+    if !is_repr_c(input) {
+        return Err(Error::new(
+            name.span(),
+            "Differentiable requires #[repr(C)], so the tangent has the same layout as the struct!",
+        ));
+    }
+    if fields.is_empty() {
+        return Err(Error::new(
+            name.span(),
+            "Differentiable requires at least one field!",
+        ));
+    }
+    if !input.generics.params.is_empty() {
+        return Err(Error::new_spanned(
+            &input.generics,
+            "Differentiable can't be derived for generic structs yet!",
+        ));
+    }
+
+    let tangent = tangent_name(name);
+    let vis = &input.vis;
+    let rt = quote! { ::autodiff_runtime };
+    let tys: Vec<_> = fields.iter().map(|field| &field.ty).collect();
+    let members: Vec<Member> = fields
+        .iter()
+        .enumerate()
+        .map(|(i, field)| match &field.ident {
+            Some(ident) => Member::Named(ident.clone()),
+            None => Member::Unnamed(Index::from(i)),
+        })
+        .collect();
+    let tangent_fields = fields.iter().map(|field| {
+        let ty = &field.ty;
+        let vis = &field.vis;
+        let ty = quote! { <#ty as #rt::Differentiable>::Tangent };
+        match &field.ident {
+            Some(ident) => quote! { #vis #ident: #ty },
+            None => quote! { #vis #ty },
+        }
+    });
+    let body = match fields {
+        Fields::Named(_) => quote! { { #(#tangent_fields),* } },
+        Fields::Unnamed(_) => quote! { ( #(#tangent_fields),* ); },
+        Fields::Unit => unreachable!("structs without fields are rejected above"),
+    };
+    let lens: Vec<TokenStream> = tys
+        .iter()
+        .map(|ty| quote! { <<#ty as #rt::Differentiable>::Tangent as #rt::Tangent>::LEN })
+        .collect();
+    // Each field is flattened at the sum of the lengths of the fields before it.
+    let offsets: Vec<TokenStream> = (0..lens.len())
+        .map(|i| {
+            let before = &lens[..i];
+            quote! { 0 #(+ #before)* }
+        })
+        .collect();
+    let init = |values: Vec<TokenStream>| match fields {
+        Fields::Named(_) => quote! { Self { #(#members: #values),* } },
+        Fields::Unnamed(_) => quote! { Self( #(#values),* ) },
+        Fields::Unit => unreachable!("structs without fields are rejected above"),
+    };
+    let zero = init(
+        members
+            .iter()
+            .map(|_| quote! { #rt::Tangent::zero() })
+            .collect(),
+    );
+    let unflatten = init(
+        offsets
+            .iter()
+            .zip(&lens)
+            .map(|(offset, len)| quote! { #rt::Tangent::unflatten(&src[#offset..#offset + #len]) })
+            .collect(),
+    );
+    let doc = format!(" The tangent of [`{name}`], generated by `#[derive(Differentiable)]`.");
+
+    Ok(quote! {
+        #[doc = #doc]
+        #[repr(C)]
+        #[derive(Clone, Debug, PartialEq)]
+        #vis struct #tangent #body
+
+        unsafe impl #rt::Differentiable for #name {
+            type Tangent = #tangent;
+        }
+
+        impl #rt::Tangent for #tangent {
+            const LEN: usize = 0 #(+ #lens)*;
+            fn zero() -> Self {
+                #zero
+            }
+            fn axpy(&mut self, alpha: f64, x: &Self) {
+                #(#rt::Tangent::axpy(&mut self.#members, alpha, &x.#members);)*
+            }
+            fn flatten_into(&self, out: &mut [f64]) {
+                #(#rt::Tangent::flatten_into(&self.#members, &mut out[#offsets..#offsets + #lens]);)*
+            }
+            fn unflatten(src: &[f64]) -> Self {
+                #unflatten
+            }
+        }
+    })
+}
@@ -0,0 +1,348 @@
+//! Human readable explanations of what we generate, for `cargo autodiff` and the generated docs.
+
+use quote::ToTokens;
+use syn::{
+    parse_quote, Attribute, FnArg, ForeignItemFn, ItemStruct, Pat, Result, ReturnType, Signature,
+    Type,
+};
+
+use crate::modes::forward::{self, FwdActivity, FwdInfo, FwdReturnActivity, RuntimeWidth};
+use crate::modes::reverse::{self, Activity, ReturnActivity, RevInfo};
+use crate::{DiffConfig, Generated, Layout};
+
+/// How one parameter of the primal shows up in the derivative.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParamInfo {
+    pub name: String,
+    pub ty: String,
+    pub activity: String,
+    /// A short sentence describing the role of the parameter in the derivative.
+    pub role: String,
+}
+
+fn param_name(param: &FnArg) -> String {
+    match param {
+        FnArg::Typed(pat_ty) => match &*pat_ty.pat {
+            Pat::Ident(pat_ident) => pat_ident.ident.to_string(),
+            pat => pat.to_token_stream().to_string(),
+        },
+        FnArg::Receiver(_) => "self".to_owned(),
+    }
+}
+
+fn param_type(param: &FnArg) -> String {
+    match param {
+        FnArg::Typed(pat_ty) => pat_ty.ty.to_token_stream().to_string(),
+        FnArg::Receiver(r) => r.to_token_stream().to_string(),
+    }
+}
+
+/// Explains the role of every parameter of `primal` in the derivative described by `config`.
+pub fn explain_params(config: &DiffConfig, primal: &Signature) -> Result<Vec<ParamInfo>> {
+    let f = &primal.ident;
+    let mut infos = vec![];
+    match config {
+        DiffConfig::Fwd(fwd) => {
+            let activities =
+                forward::resolve_activities(&fwd.input_activity, &mut scratch(primal))?;
+            let width = u32::from(fwd.width);
+            for (act, param) in activities.iter().zip(primal.inputs.iter()) {
+                let name = param_name(param);
+                let role = match act {
+                    FwdActivity::Constant => "constant, its tangent is zero".to_owned(),
+                    FwdActivity::Duplicated if matches!(fwd.runtime_width, Some(RuntimeWidth::Dyn)) => format!(
+                        "followed by `d_{name}`, a pointer to `width` tangents of `{name}`, one per direction"
+                    ),
+                    FwdActivity::Duplicated if array_tangents(fwd) => format!(
+                        "followed by its tangents `d_{name}: [_; {}]`, `d_{name}[i]` is the tangent of `{name}` in direction i",
+                        width_str(fwd)
+                    ),
+                    FwdActivity::Duplicated if width == 1 => {
+                        format!("followed by its tangent `d_{name}_0`, the direction in which we differentiate")
+                    }
+                    FwdActivity::Duplicated => format!(
+                        "followed by {width} tangents `d_{name}_0` .. `d_{name}_{}`, `d_{name}_i` is the tangent of `{name}` in direction i",
+                        width - 1
+                    ),
+                };
+                infos.push(ParamInfo {
+                    name,
+                    ty: param_type(param),
+                    activity: format!("{act:?}"),
+                    role,
+                });
+            }
+        }
+        DiffConfig::Rev(rev) => {
+            let activities = resolve_rev(rev, primal)?;
+            let returned_directly = returns_gradient_directly(rev, &activities);
+            let atomically = match rev.parallel_context {
+                true => " atomically",
+                false => "",
+            };
+            let mut active_idx = 0;
+            for (act, param) in activities.iter().zip(primal.inputs.iter()) {
+                let name = param_name(param);
+                let role = match act {
+                    Activity::Active if returned_directly => format!(
+                        "followed by a scalar factor, ∂{f}/∂{name} times that factor is returned directly"
+                    ),
+                    Activity::Active => {
+                        let field = config.field_name(active_idx, &name);
+                        active_idx += 1;
+                        format!(
+                            "followed by a scalar factor, ∂{f}/∂{name} times that factor is returned in `{field}`"
+                        )
+                    }
+                    Activity::Duplicated => format!(
+                        "followed by its shadow, to which ∂{f}/∂{name} is{atomically} added"
+                    ),
+                    Activity::Gradient => format!(
+                        "followed by its shadow, to which ∂{f}/∂{name} is{atomically} added, the primal value can't be used afterwards"
+                    ),
+                    Activity::Constant => "constant, no gradient is computed for it".to_owned(),
+                };
+                infos.push(ParamInfo {
+                    name,
+                    ty: param_type(param),
+                    activity: format!("{act:?}"),
+                    role,
+                });
+            }
+        }
+    }
+    Ok(infos)
+}
+
+/// Whether the tangents of each input are passed in one array.
+fn array_tangents(fwd: &FwdInfo) -> bool {
+    match &fwd.runtime_width {
+        Some(RuntimeWidth::Const(_)) => true,
+        Some(RuntimeWidth::Dyn) => false,
+        None => fwd.options.layout == Layout::Array,
+    }
+}
+
+fn width_str(fwd: &FwdInfo) -> String {
+    match &fwd.runtime_width {
+        Some(RuntimeWidth::Dyn) => "width".to_owned(),
+        Some(width) => width.to_string(),
+        None => fwd.width.to_string(),
+    }
+}
+
+/// Reverse mode returns the gradient of a single active input directly, without a struct.
+fn returns_gradient_directly(rev: &RevInfo, activities: &[Activity]) -> bool {
+    let n_active = activities
+        .iter()
+        .filter(|&&a| a == Activity::Active)
+        .count();
+    n_active == 1 && rev.return_activity == ReturnActivity::None
+}
+
+fn make_doc(text: &str) -> Attribute {
+    let text = format!(" {text}");
+    parse_quote! { #[doc = #text] }
+}
+
+/// Documents the generated declaration and return struct: the mode, the activity of every
+/// parameter and what the derivatives mean.
+pub(crate) fn document(
+    config: &DiffConfig,
+    primal: &Signature,
+    generated: &mut Generated,
+) -> Result<()> {
+    let f = &primal.ident;
+    let summary = match config {
+        DiffConfig::Fwd(fwd) if u32::from(fwd.width) == 1 && fwd.runtime_width.is_none() => {
+            format!("Forward mode derivative of `{f}`, computing the directional derivative (tangent) of its return value.")
+        }
+        DiffConfig::Fwd(fwd) => format!(
+            "Vector forward mode derivative of `{f}`, computing the directional derivatives (tangents) of its return value in {} directions at once.",
+            match fwd.runtime_width {
+                Some(_) => format!("`{}`", width_str(fwd)),
+                None => fwd.width.to_string(),
+            }
+        ),
+        DiffConfig::Rev(rev) if rev.parallel_context => format!(
+            "Reverse mode derivative of `{f}`, computing the gradient of its return value. The primal runs in parallel, so shadows are updated atomically."
+        ),
+        DiffConfig::Rev(_) => {
+            format!("Reverse mode derivative of `{f}`, computing the gradient of its return value.")
+        }
+    };
+    let mut docs = vec![
+        summary,
+        String::new(),
+        "# Parameters".to_owned(),
+        String::new(),
+    ];
+    for param in explain_params(config, primal)? {
+        let ty = param
+            .ty
+            .replace(" : ", ": ")
+            .replace("& ", "&")
+            .replace("* const ", "*const ")
+            .replace("* mut ", "*mut ");
+        docs.push(format!(
+            "- `{}: {ty}` ({}): {}.",
+            param.name, param.activity, param.role
+        ));
+    }
+    if let DiffConfig::Fwd(FwdInfo {
+        runtime_width: Some(RuntimeWidth::Dyn),
+        ..
+    }) = config
+    {
+        docs.push(
+            "- `d_ret`: the `width` tangents of the return value are written here.".to_owned(),
+        );
+        docs.push("- `width: usize`: the number of directions.".to_owned());
+    }
+    docs.push(String::new());
+    docs.push("# Returns".to_owned());
+    docs.push(String::new());
+    docs.push(match &generated.ret_struct {
+        Some(ret) if returns_struct(config, &generated.declaration, ret) => {
+            format!("A `{}`, see its fields.", ret.ident)
+        }
+        _ => returns(config, primal, &generated.declaration)?,
+    });
+    if generated
+        .declaration
+        .attrs
+        .iter()
+        .any(|a| a.path.is_ident("doc"))
+    {
+        // Separate our docs from the ones added by `Auto`.
+        docs.push(String::new());
+    }
+    let mut attrs: Vec<Attribute> = docs.iter().map(|line| make_doc(line)).collect();
+    attrs.append(&mut generated.declaration.attrs);
+    generated.declaration.attrs = attrs;
+
+    if let Some(ret) = &mut generated.ret_struct {
+        let name = config.name();
+        ret.attrs.push(make_doc(&format!(
+            "The values returned by `{name}`, the derivative of `{f}`."
+        )));
+        let fields = field_docs(config, primal)?;
+        for field in ret.fields.iter_mut() {
+            let ident = field.ident.as_ref().unwrap().to_string();
+            if let Some((_, doc)) = fields.iter().find(|(name, _)| *name == ident) {
+                field.attrs.push(make_doc(doc));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Does the derivative return `ret`, either from the declaration or from the wrapper of const widths?
+fn returns_struct(config: &DiffConfig, decl: &ForeignItemFn, ret: &ItemStruct) -> bool {
+    match &decl.sig.output {
+        ReturnType::Type(_, ty) => matches!(&**ty, Type::Path(p) if p.path.is_ident(&ret.ident)),
+        ReturnType::Default => matches!(
+            config,
+            DiffConfig::Fwd(FwdInfo {
+                runtime_width: Some(RuntimeWidth::Const(_)),
+                ..
+            })
+        ),
+    }
+}
+
+/// Describes the return value of a derivative which doesn't return a struct.
+fn returns(config: &DiffConfig, primal: &Signature, decl: &ForeignItemFn) -> Result<String> {
+    let f = &primal.ident;
+    if let ReturnType::Default = decl.sig.output {
+        return Ok(match config {
+            DiffConfig::Fwd(_) => "Nothing, the tangents are written to `d_ret`.".to_owned(),
+            DiffConfig::Rev(_) => "Nothing, all gradients are added to the shadows.".to_owned(),
+        });
+    }
+    if let DiffConfig::Rev(rev) = config {
+        let activities = resolve_rev(rev, primal)?;
+        if returns_gradient_directly(rev, &activities) {
+            let idx = activities
+                .iter()
+                .position(|&a| a == Activity::Active)
+                .unwrap();
+            let x = param_name(&primal.inputs[idx]);
+            return Ok(format!("∂{f}/∂{x} times the factor following `{x}`."));
+        }
+    }
+    if let DiffConfig::Fwd(FwdInfo {
+        return_activity: FwdReturnActivity::Gradient,
+        ..
+    }) = config
+    {
+        return Ok(format!("The tangent of the return value of `{f}`."));
+    }
+    Ok(format!("The primal return value of `{f}`."))
+}
+
+/// Resolving activities might annotate the declaration, so we work on a scratch copy.
+fn scratch(primal: &Signature) -> ForeignItemFn {
+    ForeignItemFn {
+        attrs: vec![],
+        vis: syn::Visibility::Inherited,
+        sig: primal.clone(),
+        semi_token: Default::default(),
+    }
+}
+
+fn resolve_rev(rev: &RevInfo, primal: &Signature) -> Result<Vec<Activity>> {
+    reverse::resolve_activities(rev.input_activity.clone(), &mut scratch(primal))
+}
+
+/// The documentation of every field which a return struct might have.
+fn field_docs(config: &DiffConfig, primal: &Signature) -> Result<Vec<(String, String)>> {
+    let f = &primal.ident;
+    let mut docs = vec![(
+        "primary_ret".to_owned(),
+        format!("The primal return value of `{f}`."),
+    )];
+    match config {
+        DiffConfig::Fwd(fwd) => {
+            let width = u32::from(fwd.width);
+            if array_tangents(fwd) {
+                docs.push((
+                    "primary_grad".to_owned(),
+                    "The tangents of the return value, `primary_grad[i]` is the one in direction i."
+                        .to_owned(),
+                ));
+            } else if width == 1 {
+                docs.push((
+                    "primary_grad".to_owned(),
+                    "The tangent of the return value, in the direction given by the input tangents."
+                        .to_owned(),
+                ));
+            }
+            for i in (0..width).filter(|_| width > 1 && !array_tangents(fwd)) {
+                docs.push((
+                    format!("primary_grad{i}"),
+                    format!("The tangent of the return value in direction {i}."),
+                ));
+            }
+        }
+        DiffConfig::Rev(rev) => {
+            docs.push((
+                "primary_grad".to_owned(),
+                format!("The gradient (adjoint) of the return value of `{f}`."),
+            ));
+            let activities = resolve_rev(rev, primal)?;
+            let active = activities
+                .iter()
+                .zip(primal.inputs.iter())
+                .filter(|(&act, _)| act == Activity::Active);
+            for (i, (_, param)) in active.enumerate() {
+                let x = param_name(param);
+                docs.push((
+                    config.field_name(i, &x).to_string(),
+                    format!("∂{f}/∂{x} times the factor following `{x}`."),
+                ));
+            }
+        }
+    }
+    Ok(docs)
+}
@@ -0,0 +1,355 @@
+//! C headers for the primal shims and derivatives, so they can be called from C or C++.
+//!
+//! The easiest way to use this is from a build script:
+//!
+//! ```no_run
+//! let out = std::path::PathBuf::from(std::env::var("OUT_DIR").unwrap()).join("autodiff.h");
+//! autodiff_codegen::header::write_header("src".as_ref(), &out).unwrap();
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use quote::ToTokens;
+use syn::{
+    Error, Expr, ExprLit, FnArg, GenericArgument, ItemStruct, Lit, Pat, PathArguments, Result,
+    ReturnType, Signature, Stmt, Type, TypePath,
+};
+
+use crate::scan::{repr_c_structs, repr_c_structs_in_dir, scan_dir, scan_file, Found, ScanError};
+
+/// Writes a header with the prototypes of all primal shims and derivatives
+/// declared in `src`, which can be a single file or a directory.
+/// The include guard is derived from the name of `out`.
+pub fn write_header(src: &Path, out: &Path) -> std::result::Result<(), ScanError> {
+    let (found, structs) = if src.is_file() {
+        (scan_file(src)?, repr_c_structs(src)?)
+    } else {
+        (scan_dir(src)?, repr_c_structs_in_dir(src)?)
+    };
+    let stem = out
+        .file_name()
+        .map(|s| s.to_string_lossy())
+        .unwrap_or_default();
+    let guard: String = stem
+        .chars()
+        .map(|c| match c.is_ascii_alphanumeric() {
+            true => c.to_ascii_uppercase(),
+            false => '_',
+        })
+        .collect();
+    let header = header(&found, &structs, &guard)?;
+    std::fs::write(out, header).map_err(|e| ScanError::Io(out.to_owned(), e))
+}
+
+/// Renders the header for the given derivatives.
+/// Array lengths given by a Rust const are used as is, so the header expects a macro
+/// of the same name.
+/// `structs` are the `#[repr(C)]` structs of the crate, of which all which are
+/// used by a primal or derivative get a C definition.
+pub fn header(
+    found: &[Found],
+    structs: &[ItemStruct],
+    guard: &str,
+) -> std::result::Result<String, ScanError> {
+    let mut writer = Writer {
+        structs: structs.iter().map(|s| (s.ident.to_string(), s)).collect(),
+        emitted: HashSet::new(),
+        typedefs: vec![],
+        definitions: vec![],
+        prototypes: vec![],
+    };
+    for f in found {
+        // Generated types have no location in the file, so we report errors at the derivative.
+        writer.add(f).map_err(|e| {
+            let name = f.config.name();
+            let e = Error::new(name.span(), format!("`{name}`: {e}"));
+            ScanError::Parse(f.file.clone(), e)
+        })?;
+    }
+
+    let mut out = String::new();
+    out.push_str("/* Generated by autodiff-codegen, do not edit. */\n");
+    out.push_str(&format!("#ifndef {guard}\n#define {guard}\n\n"));
+    out.push_str("#include <stdbool.h>\n#include <stdint.h>\n\n");
+    out.push_str("#ifdef __cplusplus\nextern \"C\" {\n#endif\n\n");
+    for section in [&writer.typedefs, &writer.definitions, &writer.prototypes] {
+        if !section.is_empty() {
+            out.push_str(&section.join("\n"));
+            out.push_str("\n\n");
+        }
+    }
+    out.push_str("#ifdef __cplusplus\n}\n#endif\n\n");
+    out.push_str(&format!("#endif /* {guard} */\n"));
+    Ok(out)
+}
+
+struct Writer<'a> {
+    structs: HashMap<String, &'a ItemStruct>,
+    emitted: HashSet<String>,
+    typedefs: Vec<String>,
+    definitions: Vec<String>,
+    prototypes: Vec<String>,
+}
+
+impl<'a> Writer<'a> {
+    fn add(&mut self, found: &'a Found) -> Result<()> {
+        let generated = &found.generated;
+        if let Some(ret) = &generated.ret_struct {
+            self.structs.insert(ret.ident.to_string(), ret);
+        }
+        let mut prototypes = vec![format!(
+            "/* {}:{}: {} */",
+            found.file.display(),
+            found.line,
+            found.config.name()
+        )];
+        if let Some(shim) = &generated.shim {
+            let symbol = found.config.options().symbol_prefix() + &shim.sig.ident.to_string();
+            prototypes.push(self.prototype(&shim.sig, &symbol)?);
+        }
+        prototypes.push(self.prototype(&generated.declaration.sig, &found.config.symbol())?);
+        self.prototypes.push(prototypes.join("\n"));
+        Ok(())
+    }
+
+    fn prototype(&mut self, sig: &Signature, symbol: &str) -> Result<String> {
+        let mut params = vec![];
+        let mut names = HashSet::new();
+        for (i, param) in sig.inputs.iter().enumerate() {
+            let pat_ty = match param {
+                FnArg::Typed(pat_ty) => pat_ty,
+                FnArg::Receiver(r) => return Err(Error::new_spanned(r, "self not supported!")),
+            };
+            let mut name = match &*pat_ty.pat {
+                Pat::Ident(pat_ident) => pat_ident.ident.to_string(),
+                _ => format!("arg{i}"),
+            };
+            // Shadows share the name of their primal argument, which C doesn't allow.
+            if names.contains(&name) {
+                name = format!("d_{name}");
+            }
+            while names.contains(&name) {
+                name.push('_');
+            }
+            names.insert(name.clone());
+            if let Type::Array(_) = &*pat_ty.ty {
+                return Err(Error::new_spanned(
+                    &pat_ty.ty,
+                    "C can't pass arrays by value, pass a reference instead!",
+                ));
+            }
+            params.push(self.declare(&pat_ty.ty, &name)?);
+        }
+        if params.is_empty() {
+            params.push("void".to_owned());
+        }
+        let declarator = format!("{symbol}({})", params.join(", "));
+        Ok(match &sig.output {
+            ReturnType::Type(_, ty) if !is_unit(ty) => {
+                if let Type::Array(_) = &**ty {
+                    return Err(Error::new_spanned(ty, "C can't return arrays by value!"));
+                }
+                self.declare(ty, &declarator)? + ";"
+            }
+            _ => format!("void {declarator};"),
+        })
+    }
+
+    /// A C declaration of `name` with the type `ty`.
+    fn declare(&mut self, ty: &Type, name: &str) -> Result<String> {
+        match ty {
+            Type::Paren(paren) => self.declare(&paren.elem, name),
+            Type::Group(group) => self.declare(&group.elem, name),
+            Type::Ptr(ptr) => self.pointer(&ptr.elem, ptr.const_token.is_some(), name),
+            Type::Reference(r) => self.pointer(&r.elem, r.mutability.is_none(), name),
+            Type::Array(array) => {
+                let len = array.len.to_token_stream();
+                self.declare(&array.elem, &format!("{name}[{len}]"))
+            }
+            // Tangents have the layout of their primal type, see `tangent_shadow`.
+            Type::Path(path) if is_tangent(path) => {
+                self.declare(&path.qself.as_ref().unwrap().ty, name)
+            }
+            Type::Path(path) if path.qself.is_none() => {
+                let seg = path.path.segments.last().unwrap();
+                if seg.ident.to_string().starts_with("__enzyme_array_") {
+                    let base = self.array_struct(ty, &seg.arguments)?;
+                    return Ok(format!("{base} {name}").trim_end().to_owned());
+                }
+                if !seg.arguments.is_empty() {
+                    return Err(Error::new_spanned(
+                        ty,
+                        "Generic types have no C equivalent!",
+                    ));
+                }
+                let ident = seg.ident.to_string();
+                let base = match c_name(&ident) {
+                    Some(base) => base.to_owned(),
+                    None if self.structs.contains_key(&ident) => {
+                        self.define(&ident)?;
+                        ident
+                    }
+                    None => {
+                        return Err(Error::new_spanned(
+                            ty,
+                            format!("`{ident}` has no known C equivalent, is it #[repr(C)]?"),
+                        ))
+                    }
+                };
+                Ok(match name.is_empty() {
+                    true => base,
+                    false => format!("{base} {name}"),
+                })
+            }
+            _ => Err(Error::new_spanned(
+                ty,
+                format!("`{}` has no C equivalent!", ty.to_token_stream()),
+            )),
+        }
+    }
+
+    fn pointer(&mut self, elem: &Type, is_const: bool, name: &str) -> Result<String> {
+        match elem {
+            // Pointers to arrays are passed as pointers to their first element.
+            Type::Array(array) => self.pointer(&array.elem, is_const, name),
+            Type::Slice(_) | Type::TraitObject(_) => Err(Error::new_spanned(
+                elem,
+                "Pointers to unsized types are not FFI-safe!",
+            )),
+            Type::Path(path) if path.path.is_ident("str") => Err(Error::new_spanned(
+                elem,
+                "Pointers to unsized types are not FFI-safe!",
+            )),
+            Type::Ptr(_) | Type::Reference(_) if is_const => {
+                self.declare(elem, &format!("const *{name}"))
+            }
+            _ if is_const => Ok(format!(
+                "const {}",
+                self.declare(elem, &format!("*{name}"))?
+            )),
+            _ => self.declare(elem, &format!("*{name}")),
+        }
+    }
+
+    /// Arrays passed by value are wrapped in a generic struct, see the `arrays` module.
+    /// C has no generics, so we emit one struct per element type and length.
+    fn array_struct(&mut self, ty: &Type, arguments: &PathArguments) -> Result<String> {
+        let args: Vec<&GenericArgument> = match arguments {
+            PathArguments::AngleBracketed(args) => args.args.iter().collect(),
+            _ => vec![],
+        };
+        let (elem, len) = match args[..] {
+            [GenericArgument::Type(elem), GenericArgument::Const(len)] => (elem, len),
+            _ => unreachable!("we generate these types ourselves"),
+        };
+        let len = match len {
+            Expr::Block(block) if block.block.stmts.len() == 1 => match &block.block.stmts[0] {
+                Stmt::Expr(Expr::Lit(ExprLit {
+                    lit: Lit::Int(len), ..
+                })) => len.base10_parse::<usize>()?.to_string(),
+                // A Rust const, which has to be defined as macro with the same name in C.
+                Stmt::Expr(Expr::Path(path)) if path.path.get_ident().is_some() => {
+                    path.path.get_ident().unwrap().to_string()
+                }
+                _ => {
+                    return Err(Error::new_spanned(
+                        ty,
+                        "C headers need array lengths as literals or plain constants!",
+                    ))
+                }
+            },
+            _ => unreachable!("we generate these types ourselves"),
+        };
+        let elem_name = self.declare(elem, "")?;
+        let ident = format!("autodiff_array_{}_{len}", elem_name.replace(' ', "_"));
+        if self.emitted.insert(ident.clone()) {
+            self.typedefs
+                .push(format!("typedef struct {ident} {ident};"));
+            let field = self.declare(elem, &format!("_0[{len}]"))?;
+            self.definitions
+                .push(format!("struct {ident} {{\n    {field};\n}};"));
+        }
+        Ok(ident)
+    }
+
+    /// Emits the definition of a struct, after the definitions of all structs it contains.
+    fn define(&mut self, ident: &str) -> Result<()> {
+        if !self.emitted.insert(ident.to_owned()) {
+            return Ok(());
+        }
+        let item = self.structs[ident];
+        if !item.generics.params.is_empty() {
+            return Err(Error::new_spanned(
+                &item.generics,
+                "Generic structs have no C equivalent!",
+            ));
+        }
+        self.typedefs
+            .push(format!("typedef struct {ident} {ident};"));
+        let mut fields = vec![];
+        for (i, field) in item.fields.iter().enumerate() {
+            let name = match &field.ident {
+                Some(name) => name.to_string(),
+                None => format!("_{i}"),
+            };
+            fields.push(format!("    {};", self.declare(&field.ty, &name)?));
+        }
+        if fields.is_empty() {
+            return Err(Error::new_spanned(
+                item,
+                "Empty structs have no C equivalent!",
+            ));
+        }
+        self.definitions
+            .push(format!("struct {ident} {{\n{}\n}};", fields.join("\n")));
+        Ok(())
+    }
+}
+
+/// Is this `<T as ::autodiff_runtime::Differentiable>::Tangent`?
+fn is_tangent(path: &TypePath) -> bool {
+    let segments: Vec<String> = path
+        .path
+        .segments
+        .iter()
+        .map(|seg| seg.ident.to_string())
+        .collect();
+    path.qself.is_some() && segments.ends_with(&["Differentiable".to_owned(), "Tangent".to_owned()])
+}
+
+fn is_unit(ty: &Type) -> bool {
+    matches!(ty, Type::Tuple(tuple) if tuple.elems.is_empty())
+}
+
+/// The C equivalent of a primitive Rust type.
+fn c_name(ident: &str) -> Option<&'static str> {
+    Some(match ident {
+        "f32" | "c_float" => "float",
+        "f64" | "c_double" => "double",
+        "bool" => "bool",
+        "i8" => "int8_t",
+        "i16" => "int16_t",
+        "i32" | "AtomicI32" => "int32_t",
+        "i64" | "AtomicI64" => "int64_t",
+        "u8" => "uint8_t",
+        "u16" => "uint16_t",
+        "u32" | "char" | "AtomicU32" => "uint32_t",
+        "u64" | "AtomicU64" => "uint64_t",
+        "isize" | "AtomicIsize" => "intptr_t",
+        "usize" | "AtomicUsize" => "uintptr_t",
+        "c_char" => "char",
+        "c_schar" => "signed char",
+        "c_uchar" => "unsigned char",
+        "c_short" => "short",
+        "c_ushort" => "unsigned short",
+        "c_int" => "int",
+        "c_uint" => "unsigned int",
+        "c_long" => "long",
+        "c_ulong" => "unsigned long",
+        "c_longlong" => "long long",
+        "c_ulonglong" => "unsigned long long",
+        "c_void" => "void",
+        _ => return None,
+    })
+}
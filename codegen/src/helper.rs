@@ -0,0 +1,269 @@
+use crate::modes::reverse::ReturnActivity;
+
+use super::DiffConfig;
+//use super::ReturnActivity::*;
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::punctuated::Punctuated;
+use syn::Token;
+use syn::*;
+
+fn derive_attr() -> Attribute {
+    Attribute {
+        pound_token: Default::default(),
+        style: AttrStyle::Outer,
+        bracket_token: Default::default(),
+        path: Path {
+            leading_colon: std::option::Option::None,
+            segments: Punctuated::new(),
+        },
+        tokens: quote! { derive(Clone,Debug) },
+    }
+}
+
+fn repr_c_attr() -> Attribute {
+    Attribute {
+        pound_token: Default::default(),
+        style: AttrStyle::Outer,
+        bracket_token: Default::default(),
+        path: Path {
+            leading_colon: std::option::Option::None,
+            segments: Punctuated::new(),
+        },
+        tokens: quote! { repr(C) },
+    }
+}
+
+// This will be parsed into the TokenStream.
+// We need to define a new return struct,
+// since tuples are not stable / usable trough the c-abi.
+//pub fn create_ret_struct(grad_name: Ident, sig: syn::Signature) -> syn::ItemStruct {
+pub(crate) fn create_ret_struct(grad_info: DiffConfig, sig: syn::Signature) -> syn::ItemStruct {
+    let generics = sig.generics;
+    let attrs: Vec<syn::Attribute> = vec![repr_c_attr(), derive_attr()];
+    let vis = syn::Visibility::Inherited;
+    let struct_token: Token![struct] = Default::default();
+    let ident = grad_info.ret_name();
+    let mut fields_named = FieldsNamed {
+        brace_token: Default::default(),
+        named: Punctuated::new(),
+    };
+    // If our primary function returns something, we might ad that to our return struct
+    if let ReturnType::Type(_, box_ty) = sig.output {
+        match grad_info.ret() {
+            ReturnActivity::None => unreachable!(), // No primary return value exists.
+            ReturnActivity::Gradient | ReturnActivity::Ignore => {} // The primary return value will be optimized away.
+            ReturnActivity::Active | ReturnActivity::Constant => {
+                // for all other cases, append the primary return value to our return struct
+                let primary_id = Ident::new("primary_ret", proc_macro2::Span::mixed_site());
+                let field = Field {
+                    attrs: vec![],
+                    vis: Visibility::Inherited,
+                    ident: Some(primary_id),
+                    colon_token: Default::default(),
+                    ty: *box_ty,
+                };
+                fields_named.named.push(field);
+            }
+        }
+    }
+    let fields = Fields::Named(fields_named);
+    let semi_token = std::option::Option::None;
+    ItemStruct {
+        attrs,
+        vis,
+        struct_token,
+        ident,
+        generics,
+        fields,
+        semi_token,
+    }
+}
+
+/// Applies the visibility and derives from the options to a return struct,
+/// and generates the element-wise algebra for it if requested.
+pub(crate) fn finish_ret_struct(
+    grad_info: &DiffConfig,
+    primal_vis: &Visibility,
+    ret: &mut ItemStruct,
+) -> Vec<ItemImpl> {
+    let options = grad_info.options();
+    let vis = options.vis.clone().unwrap_or_else(|| primal_vis.clone());
+    ret.vis = vis.clone();
+    for field in ret.fields.iter_mut() {
+        field.vis = vis.clone();
+    }
+    if !options.derive.is_empty() {
+        let derives = &options.derive;
+        ret.attrs.push(parse_quote! { #[derive(#(#derives),*)] });
+    }
+    if !options.algebra {
+        return vec![];
+    }
+
+    let ident = &ret.ident;
+    let (impl_generics, ty_generics, where_clause) = ret.generics.split_for_impl();
+    let mut sums = vec![];
+    let mut add_assigns = vec![];
+    let mut products = vec![];
+    let mut scalar_types: Vec<&Type> = vec![];
+    for field in ret.fields.iter() {
+        let name = field.ident.as_ref().unwrap();
+        let sum = Ident::new("__sum", proc_macro2::Span::mixed_site());
+        let add_sum = add_into(quote! { #sum }, quote! { rhs.#name }, &field.ty, 0);
+        sums.push(match field.ty {
+            Type::Array(_) => quote! { #name: { let mut #sum = self.#name; #add_sum #sum } },
+            _ => quote! { #name: self.#name + rhs.#name },
+        });
+        add_assigns.push(add_into(
+            quote! { self.#name },
+            quote! { rhs.#name },
+            &field.ty,
+            0,
+        ));
+        products.push(scale(quote! { self.#name }, &field.ty, 0));
+        let scalar = scalar_type(&field.ty);
+        if !scalar_types.contains(&scalar) {
+            scalar_types.push(scalar);
+        }
+    }
+    // The scalar is an additional generic parameter, so we can't reuse `impl_generics` there.
+    let mut mul_generics = ret.generics.clone();
+    mul_generics
+        .params
+        .push(parse_quote! { __S: ::core::marker::Copy });
+    let mul_where = mul_generics.make_where_clause();
+    for ty in &scalar_types {
+        mul_where
+            .predicates
+            .push(parse_quote! { #ty: ::core::ops::Mul<__S, Output = #ty> });
+    }
+    let (mul_impl_generics, _, mul_where_clause) = mul_generics.split_for_impl();
+    let fields = ret.fields.iter().map(|f| f.ident.as_ref().unwrap());
+    vec![
+        parse_quote! {
+            impl #impl_generics ::core::ops::Add for #ident #ty_generics #where_clause {
+                type Output = Self;
+                fn add(self, rhs: Self) -> Self {
+                    Self { #(#sums),* }
+                }
+            }
+        },
+        parse_quote! {
+            impl #impl_generics ::core::ops::AddAssign for #ident #ty_generics #where_clause {
+                fn add_assign(&mut self, rhs: Self) {
+                    #(#add_assigns)*
+                }
+            }
+        },
+        parse_quote! {
+            impl #mul_impl_generics ::core::ops::Mul<__S> for #ident #ty_generics #mul_where_clause {
+                type Output = Self;
+                fn mul(self, rhs: __S) -> Self {
+                    Self { #(#fields: #products),* }
+                }
+            }
+        },
+    ]
+}
+
+/// The element type of (nested) arrays, which the algebra of the return struct works on.
+fn scalar_type(ty: &Type) -> &Type {
+    match ty {
+        Type::Array(arr) => scalar_type(&arr.elem),
+        _ => ty,
+    }
+}
+
+/// Adds `rhs` to the place `lhs`, element-wise for arrays of the return struct.
+fn add_into(lhs: TokenStream, rhs: TokenStream, ty: &Type, depth: usize) -> TokenStream {
+    match ty {
+        Type::Array(arr) => {
+            let l = Ident::new(&format!("__l{depth}"), proc_macro2::Span::mixed_site());
+            let r = Ident::new(&format!("__r{depth}"), proc_macro2::Span::mixed_site());
+            let inner = add_into(quote! { *#l }, quote! { #r }, &arr.elem, depth + 1);
+            quote! {
+                for (#l, #r) in (#lhs).iter_mut().zip(#rhs) {
+                    #inner
+                }
+            }
+        }
+        _ => quote! { #lhs += #rhs; },
+    }
+}
+
+/// Multiplies `value` by the scalar `rhs`, element-wise for arrays of the return struct.
+fn scale(value: TokenStream, ty: &Type, depth: usize) -> TokenStream {
+    match ty {
+        Type::Array(arr) => {
+            let x = Ident::new(&format!("__x{depth}"), proc_macro2::Span::mixed_site());
+            let inner = scale(quote! { #x }, &arr.elem, depth + 1);
+            quote! { #value.map(|#x| #inner) }
+        }
+        _ => quote! { #value * rhs },
+    }
+}
+
+/// A declaration which was hidden behind a Rust wrapper, see `hide_declaration`.
+pub(crate) struct Hidden {
+    /// The new name of the declaration, which the wrapper calls.
+    pub(crate) raw: Ident,
+    /// The documentation of the declaration, which now belongs to the wrapper.
+    pub(crate) docs: Vec<Attribute>,
+    pub(crate) vis: Visibility,
+    /// The signature of the declaration under its previous name, as starting point for the wrapper.
+    pub(crate) sig: Signature,
+}
+
+/// Renames the declaration to `__enzyme_raw_<name>` and makes it private and hidden,
+/// so a Rust wrapper can take its name. The symbol stays the same.
+pub(crate) fn hide_declaration(decl: &mut ForeignItemFn) -> Hidden {
+    let name = decl.sig.ident.clone();
+    let raw = Ident::new(&format!("__enzyme_raw_{name}"), name.span());
+    if !decl
+        .attrs
+        .iter()
+        .any(|attr| attr.path.is_ident("link_name"))
+    {
+        let symbol = name.to_string();
+        decl.attrs.push(parse_quote! { #[link_name = #symbol] });
+    }
+    // The documentation belongs to the wrapper, which is what users call.
+    let (docs, mut attrs): (Vec<_>, Vec<_>) = std::mem::take(&mut decl.attrs)
+        .into_iter()
+        .partition(|attr| attr.path.is_ident("doc"));
+    attrs.push(parse_quote! { #[doc(hidden)] });
+    decl.attrs = attrs;
+    let mut sig = decl.sig.clone();
+    sig.unsafety = Some(Default::default());
+    decl.sig.ident = raw.clone();
+    let vis = std::mem::replace(&mut decl.vis, Visibility::Inherited);
+    Hidden {
+        raw,
+        docs,
+        vis,
+        sig,
+    }
+}
+
+/// Shadows share the name of their primal argument, which is fine for a declaration,
+/// but not for the parameters of a function with a body.
+/// Renames the second `x` to `d_x`.
+pub(crate) fn dedup_param_names(sig: &mut Signature) {
+    let mut names = vec![];
+    for param in sig.inputs.iter_mut() {
+        if let FnArg::Typed(pat_ty) = param {
+            if let syn::Pat::Ident(pat_ident) = &mut *pat_ty.pat {
+                let mut name = pat_ident.ident.to_string();
+                if names.contains(&name) {
+                    name = format!("d_{name}");
+                }
+                while names.contains(&name) {
+                    name.push('_');
+                }
+                pat_ident.ident = Ident::new(&name, pat_ident.ident.span());
+                names.push(name);
+            }
+        }
+    }
+}
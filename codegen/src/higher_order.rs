@@ -0,0 +1,79 @@
+//! Higher order derivatives, `#[differentiate_ext(dd_f, ..., of = d_f)]`.
+//!
+//! The derivative `d_f` is declared by another attribute on the same primal `f`.
+//! We generate its declaration like that attribute does, and use its signature as primal:
+//! the shim `__enzyme_primal_d_f` calls `d_f`, and the backend differentiates that shim.
+//! Since `d_f` can have an `of` option itself, this nests to any order.
+
+use syn::{Error, ForeignItemFn, Ident, ItemFn, Result};
+
+use crate::helper::dedup_param_names;
+use crate::{
+    attrs_below, check_collisions, check_ret_collisions, generate, generate_for, is_diff_attr,
+    sibling_configs, DiffConfig, Generated,
+};
+
+/// Generates the derivative `config` of the derivative `of` of `primal`.
+pub(crate) fn generate_of(config: DiffConfig, primal: &ItemFn, of: &Ident) -> Result<Generated> {
+    let siblings = sibling_configs(primal)?;
+    let target = siblings
+        .iter()
+        .find(|sibling| sibling.name() == *of)
+        .ok_or_else(|| {
+            Error::new(
+                of.span(),
+                format!("No derivative `{of}` found, please place this attribute above the differentiate_ext attribute declaring it!"),
+            )
+        })?;
+    let inner = generate(target.clone(), &attrs_below(primal, of))?;
+    // What we differentiate is the derivative as users see it, so the wrapper if there is one.
+    let mut sig = match &inner.wrapper {
+        Some(wrapper) => wrapper.sig.clone(),
+        None => inner.declaration.sig.clone(),
+    };
+    sig.unsafety = Some(Default::default());
+    dedup_param_names(&mut sig);
+
+    // Other derivatives of `of` below us are our siblings, the innermost of them generates the shim.
+    let attrs = primal
+        .attrs
+        .iter()
+        .filter(|attr| is_diff_attr(attr))
+        .filter(|attr| {
+            attr.parse_args::<DiffConfig>()
+                .is_ok_and(|sibling| sibling.options().of.as_ref() == Some(of))
+        })
+        .cloned()
+        .collect();
+    let derivative = ItemFn {
+        attrs,
+        vis: primal.vis.clone(),
+        sig,
+        block: Box::new(syn::parse_quote! { {} }),
+    };
+    let mut generated = generate_for(config.clone(), &derivative, &of.clone().into())?;
+
+    // So far we were only compared with the other derivatives of `of`,
+    // but our name and return struct can as well collide with the ones of `primal`.
+    let mut config = config;
+    config.resolve_name(of)?;
+    let others: Vec<DiffConfig> = siblings
+        .into_iter()
+        .filter(|sibling| sibling.options().of.as_ref() != Some(of))
+        .collect();
+    check_collisions(&config, &primal.sig.ident, &others)?;
+    if let Some(ret) = &generated.ret_struct {
+        let mut unadjusted = ForeignItemFn {
+            attrs: vec![],
+            vis: primal.vis.clone(),
+            sig: primal.sig.clone(),
+            semi_token: Default::default(),
+        };
+        unadjusted.sig.unsafety = None;
+        unadjusted.sig.abi = None;
+        check_ret_collisions(&config, ret, &unadjusted, &others)?;
+    }
+    // The attribute still emits the original primal, not the derivative we differentiated.
+    generated.primal = primal.clone();
+    Ok(generated)
+}
@@ -0,0 +1,654 @@
+//! The code generator behind the `autodiff` macros.
+//!
+//! All the logic which derives the declaration of a derivative from the signature of the primal
+//! function lives here, in an ordinary library.
+//! That way build scripts, command line tools or other frontends can reuse exactly
+//! the same signature derivation as the `differentiate_ext` attribute.
+//!
+//! ```
+//! let config: autodiff_codegen::DiffConfig =
+//!     syn::parse_str("d_f, Reverse, All(Active), Active, false").unwrap();
+//! let primal: syn::ItemFn = syn::parse_str("fn f(x: f64) -> f64 { x * x }").unwrap();
+//! let generated = autodiff_codegen::generate(config, &primal).unwrap();
+//! assert_eq!(generated.declaration.sig.ident, "d_f");
+//! ```
+
+#![doc(html_logo_url = "https://enzyme.mit.edu//logo.svg")]
+
+use modes::{forward, reverse};
+use proc_macro2::TokenStream as TS2;
+use quote::*;
+use syn::token;
+use syn::*;
+
+pub mod annotations;
+mod arrays;
+mod buffers;
+pub mod callbacks;
+pub mod declare;
+pub mod differentiable;
+pub mod types;
+pub use types::{DiffConfig, Layout, Mode, Options, Width};
+pub mod explain;
+pub mod header;
+#[doc(hidden)]
+mod helper;
+mod higher_order;
+mod metadata;
+pub mod modes;
+pub mod scan;
+pub mod trajectory;
+pub mod type_tree;
+
+/// Everything we generate for one `differentiate_ext` attribute.
+#[derive(Clone)]
+pub struct Generated {
+    /// The primal function, which we emit unchanged.
+    pub primal: ItemFn,
+    /// The `extern "C"` shim around the primal, which Enzyme differentiates.
+    /// Only the innermost of multiple stacked attributes generates it.
+    pub shim: Option<ItemFn>,
+    /// The `#[repr(C)]` struct which wraps arrays passed by value, generated next to the shim.
+    pub array_struct: Option<ItemStruct>,
+    /// The declaration of the derivative, which will later be filled in by Enzyme.
+    pub declaration: ForeignItemFn,
+    /// The settings for the backend which it can't read from the declaration.
+    pub metadata: ItemStatic,
+    /// The struct returned by the derivative, if it returns more than one value.
+    pub ret_struct: Option<ItemStruct>,
+    /// Trait implementations for the return struct, see the `algebra` option.
+    pub ret_impls: Vec<ItemImpl>,
+    /// A Rust wrapper around the declaration, e.g. for `layout = array`.
+    /// Users call it instead of the declaration, which is then hidden.
+    pub wrapper: Option<ItemFn>,
+    /// The constant building the type trees of the primal parameters and the static holding them,
+    /// see the `type_tree` option.
+    pub type_tree: Vec<Item>,
+    /// The function pointer types of the declaration and its primal, so the derivative can be
+    /// the shadow of a callback, and the checks of the callbacks it takes itself.
+    pub callbacks: Vec<Item>,
+}
+
+impl Generated {
+    /// The generated documentation of the declaration and the return struct, as plain text.
+    pub fn explanation(&self) -> String {
+        let doc_lines = |attrs: &[Attribute]| -> Vec<String> {
+            attrs
+                .iter()
+                .filter_map(|attr| match attr.parse_meta() {
+                    Ok(Meta::NameValue(MetaNameValue {
+                        path,
+                        lit: Lit::Str(text),
+                        ..
+                    })) if path.is_ident("doc") => Some(text.value().trim_start().to_owned()),
+                    _ => None,
+                })
+                .collect()
+        };
+        let decl = &self.declaration.sig.ident;
+        let mut lines = vec![format!("`{decl}`:")];
+        lines.extend(doc_lines(&self.declaration.attrs));
+        if let Some(ret) = &self.ret_struct {
+            lines.push(String::new());
+            lines.push(format!("`{}`:", ret.ident));
+            lines.extend(doc_lines(&ret.attrs));
+            for field in ret.fields.iter() {
+                let field_docs = doc_lines(&field.attrs).join(" ");
+                lines.push(format!(
+                    "- `{}`: {field_docs}",
+                    field.ident.as_ref().unwrap()
+                ));
+            }
+        }
+        lines.join("\n")
+    }
+
+    /// Everything we generate besides the primal, e.g. for `declare_derivative!`,
+    /// where the primal is defined elsewhere.
+    pub fn derivative_tokens(&self) -> TS2 {
+        let Generated {
+            primal: _,
+            shim,
+            array_struct,
+            declaration,
+            metadata,
+            ret_struct,
+            ret_impls,
+            wrapper,
+            type_tree,
+            callbacks,
+        } = self;
+        quote! {
+            #shim
+            #array_struct
+            extern "C" { #declaration }
+            #wrapper
+            #metadata
+            #(#type_tree)*
+            #(#callbacks)*
+            #ret_struct
+            #(#ret_impls)*
+        }
+    }
+}
+
+impl ToTokens for Generated {
+    fn to_tokens(&self, tokens: &mut TS2) {
+        self.primal.to_tokens(tokens);
+        tokens.extend(self.derivative_tokens());
+    }
+}
+
+#[doc(hidden)]
+/// Name of the `extern "C"` shim which we generate around the primal.
+pub fn shim_name(primal: &Ident) -> Ident {
+    Ident::new(&format!("__enzyme_primal_{primal}"), primal.span())
+}
+
+#[doc(hidden)]
+/// Name of the shim calling `callee`, with its whole path mangled in,
+/// so functions of the same name in different modules get different shims.
+fn callee_shim_name(callee: &Path) -> Ident {
+    let segments: Vec<String> = callee
+        .segments
+        .iter()
+        .map(|seg| seg.ident.to_string())
+        .collect();
+    let last = &callee.segments.last().unwrap().ident;
+    shim_name(&Ident::new(&segments.join("__"), last.span()))
+}
+
+#[doc(hidden)]
+/// Is this attribute one of ours?
+pub fn is_diff_attr(attr: &Attribute) -> bool {
+    attr.path
+        .segments
+        .last()
+        .is_some_and(|seg| seg.ident == "differentiate_ext")
+}
+
+#[doc(hidden)]
+/// Generates an `extern "C"` wrapper around the primal function.
+///
+/// Enzyme differentiates this shim rather than the primal itself.
+/// That way the primal and the derivative are both using the C-ABI,
+/// so arguments are guaranteed to be passed the same way.
+/// The user function itself stays untouched, so it keeps the Rust-ABI and its mangled name.
+fn create_primal_shim(
+    primal: &ItemFn,
+    callee: &Path,
+    prefix: &str,
+    buffers: &[Path],
+) -> Result<ItemFn> {
+    let sig = &primal.sig;
+    if let Some(param) = sig.generics.const_params().next() {
+        return Err(Error::new_spanned(
+            param,
+            "Functions which are generic over array lengths can't be differentiated through the C-ABI, which has no generic symbols! Please differentiate a wrapper with a concrete length, e.g. taken from a const item.",
+        ));
+    }
+    if sig.generics.type_params().next().is_some() {
+        return Err(Error::new_spanned(
+            &sig.generics,
+            "Generic functions can't be differentiated through the C-ABI!",
+        ));
+    }
+    let mut shim_sig = sig.clone();
+    shim_sig.ident = callee_shim_name(callee);
+    // Callers have to uphold the requirements of the primal, and pass valid pointers and lengths.
+    shim_sig.unsafety = Some(Default::default());
+    shim_sig.abi = Some(parse_quote! { extern "C" });
+    shim_sig.asyncness = None;
+    shim_sig.constness = None;
+
+    for (i, param) in shim_sig.inputs.iter_mut().enumerate() {
+        let pat_ty = match param {
+            FnArg::Typed(pat_ty) => pat_ty,
+            FnArg::Receiver(r) => return Err(Error::new_spanned(r, "self not supported!")),
+        };
+        let arg = match &*pat_ty.pat {
+            Pat::Ident(pat_ident) => pat_ident.ident.clone(),
+            _ => Ident::new(&format!("arg{i}"), proc_macro2::Span::mixed_site()),
+        };
+        *pat_ty.pat = parse_quote! { #arg };
+    }
+
+    let primal_name = &sig.ident;
+    let buffers = buffers::buffer_params(sig, buffers);
+    buffers::reject_mutable(&buffers)?;
+    let names: Vec<Option<Ident>> = shim_sig
+        .inputs
+        .iter()
+        .map(|param| match param {
+            FnArg::Typed(pat_ty) => match &*pat_ty.pat {
+                Pat::Ident(pat_ident) => Some(pat_ident.ident.clone()),
+                _ => None,
+            },
+            FnArg::Receiver(_) => None,
+        })
+        .collect();
+    // Arrays passed by value are wrapped in a struct, see the arrays module.
+    let body = arrays::wrap_shim(&mut shim_sig, primal_name, |args| {
+        // Containers are rebuilt from a pointer and a length, see the buffers module.
+        let args = args
+            .iter()
+            .zip(&names)
+            .map(|(arg, name)| buffers::shim_arg(arg, name.as_ref(), &buffers));
+        let call = quote! { #callee(#(#args),*) };
+        match sig.unsafety {
+            Some(_) => quote! { unsafe { #call } },
+            None => call,
+        }
+    });
+    let views = buffers::lower_shim(&mut shim_sig, &buffers);
+    let export = export_attr(&shim_sig.ident, prefix);
+    Ok(parse_quote! {
+        #[doc(hidden)]
+        #export
+        #[inline(never)]
+        #shim_sig {
+            #views
+            #body
+        }
+    })
+}
+
+#[doc(hidden)]
+/// Exports an item under its own name, or under a prefixed name if a prefix is given.
+fn export_attr(ident: &Ident, prefix: &str) -> Attribute {
+    if prefix.is_empty() {
+        parse_quote! { #[no_mangle] }
+    } else {
+        let symbol = format!("{prefix}{ident}");
+        parse_quote! { #[export_name = #symbol] }
+    }
+}
+
+#[doc(hidden)]
+/// Reports derivative names which would clash with the primal,
+/// with the derivatives declared by the other `differentiate_ext` attributes on the same function,
+/// or with the return structs generated for them.
+///
+/// Each attribute only sees the attributes below it, so every pair is checked exactly once.
+pub(crate) fn check_collisions(
+    input: &DiffConfig,
+    primal: &Ident,
+    siblings: &[DiffConfig],
+) -> Result<()> {
+    let name = input.name();
+    let err = |msg: String| Err(Error::new(name.span(), msg));
+    if name == *primal || name == shim_name(primal) {
+        return err(format!(
+            "Please give the gradient function to be generated a new name, `{name}` is already used by the primal function!"
+        ));
+    }
+    for sibling in siblings {
+        let other = sibling.name();
+        if other == name {
+            return err(format!(
+                "The derivative name `{name}` is used by multiple differentiate_ext attributes on `{primal}`!"
+            ));
+        }
+        if name == sibling.ret_name() || other == input.ret_name() {
+            return err(format!(
+                "The derivative names `{name}` and `{other}` collide with each others return struct!"
+            ));
+        }
+        if input.symbol() == sibling.symbol() {
+            return err(format!(
+                "The derivatives `{name}` and `{other}` would both be exported as `{}`!",
+                input.symbol()
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[doc(hidden)]
+/// Reports return structs which share their name with the one of a stacked sibling,
+/// or which would get multiple fields of the same name, e.g. from constant naming templates.
+///
+/// `unadjusted` is the declaration before adjusting its parameters to the activities.
+pub(crate) fn check_ret_collisions(
+    input: &DiffConfig,
+    ret: &ItemStruct,
+    unadjusted: &ForeignItemFn,
+    siblings: &[DiffConfig],
+) -> Result<()> {
+    let name = input.name();
+    let mut fields: Vec<&Ident> = vec![];
+    for field in ret.fields.iter().filter_map(|field| field.ident.as_ref()) {
+        if fields.contains(&field) {
+            return Err(Error::new(
+                name.span(),
+                format!("The return struct `{}` of `{name}` would have multiple fields named `{field}`, please use `{{i}}` or `{{arg}}` in the `field` template!", ret.ident),
+            ));
+        }
+        fields.push(field);
+    }
+    for sibling in siblings {
+        if sibling.ret_name() != ret.ident {
+            continue;
+        }
+        // Errors of the sibling are reported by its own attribute.
+        let sibling_ret = adjust_parameters(sibling.clone(), &mut unadjusted.clone());
+        if let Ok(Some(_)) = sibling_ret {
+            return Err(Error::new(
+                name.span(),
+                format!("The derivatives `{name}` and `{}` would both return a struct named `{}`, please use `{{name}}` in the `ret` template!", sibling.name(), ret.ident),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Parses the configurations of all `differentiate_ext` attributes which are still on the primal.
+///
+/// When stacking attributes, these are the ones below the attribute which is currently expanded.
+pub fn sibling_configs(primal: &ItemFn) -> Result<Vec<DiffConfig>> {
+    primal
+        .attrs
+        .iter()
+        .filter(|attr| is_diff_attr(attr))
+        .map(|attr| {
+            let mut sibling: DiffConfig = attr.parse_args()?;
+            sibling.resolve_name(&primal.sig.ident)?;
+            Ok(sibling)
+        })
+        .collect()
+}
+
+#[doc(hidden)]
+/// `primal` with only the `differentiate_ext` attributes below the one declaring `derivative`,
+/// as that attribute sees it.
+pub(crate) fn attrs_below(primal: &ItemFn, derivative: &Ident) -> ItemFn {
+    let mut below = primal.clone();
+    let mut found = false;
+    below.attrs.retain(|attr| {
+        if found || !is_diff_attr(attr) {
+            return true;
+        }
+        found = attr
+            .parse_args::<DiffConfig>()
+            .and_then(|mut sibling| {
+                sibling.resolve_name(&primal.sig.ident)?;
+                Ok(sibling.name() == *derivative)
+            })
+            .unwrap_or(false);
+        false
+    });
+    below
+}
+
+/// Generates the derivative declaration (and everything around it) for one configuration.
+///
+/// Other `differentiate_ext` attributes which are still on `primal` are taken into account,
+/// to detect collisions and to generate the shim around the primal only once.
+pub fn generate(config: DiffConfig, primal: &ItemFn) -> Result<Generated> {
+    if let Some(of) = config.options().of.clone() {
+        return higher_order::generate_of(config, primal, &of);
+    }
+    generate_for(config, primal, &primal.sig.ident.clone().into())
+}
+
+/// Like `generate`, but the shim calls `callee` instead of `primal`,
+/// which then only provides the signature, see `declare`.
+pub(crate) fn generate_for(
+    mut config: DiffConfig,
+    primal: &ItemFn,
+    callee: &Path,
+) -> Result<Generated> {
+    let primal_name = &primal.sig.ident;
+    config.resolve_name(primal_name)?;
+    let siblings = sibling_configs(primal)?;
+    check_collisions(&config, primal_name, &siblings)?;
+    // The shim is generated by the innermost attribute, so that's where it gets its prefix from.
+    let innermost = siblings.last().unwrap_or(&config).options();
+    let shim_prefix = innermost.symbol_prefix();
+    if innermost.buffer != config.options().buffer {
+        return Err(Error::new(
+            config.name().span(),
+            "Please list the same types in the `buffer` option of all stacked differentiate_ext attributes, they share one primal shim!",
+        ));
+    }
+    let shim_symbol = shim_prefix.clone() + &callee_shim_name(callee).to_string();
+    let mut fnc = ForeignItemFn {
+        semi_token: token::Semi::default(),
+        attrs: vec![],
+        vis: primal.vis.clone(),
+        sig: primal.sig.clone(),
+    };
+    // Declarations in extern blocks are implicitly unsafe and use the ABI of the block,
+    // e.g. for `extern "C"` primals which are passed as callbacks.
+    fnc.sig.unsafety = None;
+    fnc.sig.abi = None;
+    let metadata = config.metadata(&shim_symbol);
+    // Only the last of multiple stacked attributes generates the shim,
+    // so we don't end up with duplicated symbols.
+    let shim = match siblings.is_empty() {
+        true => Some(create_primal_shim(
+            primal,
+            callee,
+            &shim_prefix,
+            &config.options().buffer,
+        )?),
+        false => None,
+    };
+    let prefix = config.options().symbol_prefix();
+    if !prefix.is_empty() {
+        let symbol = config.symbol();
+        fnc.attrs.push(parse_quote! { #[link_name = #symbol] });
+    }
+    adjust_name(config.name(), &mut fnc);
+    let meta_static = metadata.to_static(&fnc.sig.ident, &prefix);
+    let type_tree = match config.options().type_tree {
+        true => type_tree::items(
+            &fnc.sig.ident,
+            &primal.sig,
+            &config.options().buffer,
+            &prefix,
+        )?,
+        false => vec![],
+    };
+    let unadjusted = fnc.clone();
+    let mut ret_struct = adjust_parameters(config.clone(), &mut fnc)?;
+    if let Some(ret) = &ret_struct {
+        check_ret_collisions(&config, ret, &unadjusted, &siblings)?;
+    }
+    let ret_impls = match &mut ret_struct {
+        Some(ret) => helper::finish_ret_struct(&config, &fnc.vis, ret),
+        None => vec![],
+    };
+    let array_struct = match shim.is_some() && arrays::has_arrays(&primal.sig) {
+        true => Some(arrays::array_struct(primal_name)),
+        false => None,
+    };
+    let mut generated = Generated {
+        primal: primal.clone(),
+        shim,
+        array_struct,
+        declaration: fnc,
+        metadata: meta_static,
+        ret_struct,
+        ret_impls,
+        wrapper: None,
+        type_tree,
+        callbacks: vec![],
+    };
+    explain::document(&config, &primal.sig, &mut generated)?;
+    if let DiffConfig::Fwd(fwd) = &config {
+        let const_width = matches!(fwd.runtime_width, Some(forward::RuntimeWidth::Const(_)));
+        if fwd.options.layout == Layout::Array && fwd.runtime_width.is_none() || const_width {
+            let wrapper = forward::wrapper(fwd, &primal.sig, &mut generated.declaration)?;
+            generated.wrapper = Some(wrapper);
+        }
+    }
+    buffers::wrap_declaration(&config, &primal.sig, &mut generated)?;
+    arrays::wrap_declaration(primal_name, &mut generated)?;
+    callbacks::apply(&config, &primal.sig, &mut generated)?;
+    Ok(generated)
+}
+
+/// Generates the derivative declaration of a function declared in an `extern` block,
+/// e.g. a C function which the backend gets as bitcode.
+///
+/// The backend differentiates the foreign function itself, so there is no shim.
+/// Attributes on foreign items can only expand to foreign items, so derivatives which need a
+/// return struct, a wrapper or other items next to the declaration are rejected.
+/// For the same reason no metadata is emitted, the backend uses its defaults for them.
+/// Placing the attribute on the extern block instead lifts most of these limits,
+/// see `generate_extern_block`.
+pub fn generate_foreign(config: DiffConfig, primal: &ForeignItemFn) -> Result<ForeignItemFn> {
+    foreign_derivative(config, primal).map(|generated| generated.declaration)
+}
+
+/// Like `generate_foreign`, but returns everything we would generate, for inspection.
+pub(crate) fn foreign_derivative(config: DiffConfig, primal: &ForeignItemFn) -> Result<Generated> {
+    let options = config.options();
+    if options.type_tree || !options.callback.is_empty() {
+        return Err(Error::new(
+            config.name().span(),
+            "`type_tree` and `callback` need items next to the declaration, which can't be emitted in extern blocks! Please place the attribute on the extern block instead.",
+        ));
+    }
+    let generated =
+        generate_foreign_fn(config, &primal.attrs, primal, parse_quote! { extern "C" })?;
+    let name = &generated.declaration.sig.ident;
+    if generated.ret_struct.is_some() {
+        return Err(Error::new(
+            name.span(),
+            format!("`{name}` would return a struct, which can't be declared in an extern block! Please pick activities with at most one return value, or place the attribute on the extern block instead."),
+        ));
+    }
+    Ok(generated)
+}
+
+/// Like `generate_foreign`, for the attribute on an extern block with a single function,
+/// `#[differentiate_ext(..)] extern "C" { fn f(x: f64) -> f64; }`.
+///
+/// Outside of the block we can emit the metadata, the return struct, type trees and callback
+/// aliases next to the declaration, like for functions with a body. Wrappers are still rejected,
+/// since they would convert the parameters of a shim which foreign functions don't have.
+/// Returns everything we generate besides the block itself.
+pub fn generate_extern_block(config: DiffConfig, block: &ItemForeignMod) -> Result<TS2> {
+    let Generated {
+        declaration,
+        metadata,
+        ret_struct,
+        ret_impls,
+        type_tree,
+        callbacks,
+        ..
+    } = extern_block_derivative(config, block)?;
+    Ok(quote! {
+        extern "C" { #declaration }
+        #metadata
+        #(#type_tree)*
+        #(#callbacks)*
+        #ret_struct
+        #(#ret_impls)*
+    })
+}
+
+/// Like `generate_extern_block`, but returns everything we generate, for inspection.
+pub(crate) fn extern_block_derivative(
+    config: DiffConfig,
+    block: &ItemForeignMod,
+) -> Result<Generated> {
+    let mut fns = block.items.iter().filter_map(|item| match item {
+        ForeignItem::Fn(f) => Some(f),
+        _ => None,
+    });
+    let primal = match (fns.next(), fns.next()) {
+        (Some(primal), None) => primal,
+        _ => {
+            return Err(Error::new_spanned(
+                &block.abi,
+                "Please place differentiate_ext on an extern block with exactly one function, or on the function itself!",
+            ))
+        }
+    };
+    // Stacked attributes are still on the block, the ones of the function itself aren't ours.
+    let mut attrs: Vec<Attribute> = block
+        .attrs
+        .iter()
+        .filter(|attr| is_diff_attr(attr))
+        .cloned()
+        .collect();
+    attrs.extend(
+        primal
+            .attrs
+            .iter()
+            .filter(|attr| !is_diff_attr(attr))
+            .cloned(),
+    );
+    let mut resolved = config.clone();
+    resolved.resolve_name(&primal.sig.ident)?;
+    let mut generated = generate_foreign_fn(config, &attrs, primal, block.abi.clone())?;
+    // The backend differentiates the foreign symbol, not a shim.
+    let symbol = primal
+        .attrs
+        .iter()
+        .find_map(|attr| match attr.parse_meta() {
+            Ok(Meta::NameValue(MetaNameValue {
+                path,
+                lit: Lit::Str(symbol),
+                ..
+            })) if path.is_ident("link_name") => Some(symbol.value()),
+            _ => None,
+        })
+        .unwrap_or_else(|| primal.sig.ident.to_string());
+    let prefix = resolved.options().symbol_prefix();
+    generated.metadata = resolved
+        .metadata(&symbol)
+        .to_static(&generated.declaration.sig.ident, &prefix);
+    Ok(generated)
+}
+
+/// Generates the derivative of a foreign function, as if it had `attrs` and an empty body.
+/// `abi` is the one of its extern block. Rejects what needs a shim, which foreign functions don't have.
+fn generate_foreign_fn(
+    config: DiffConfig,
+    attrs: &[Attribute],
+    primal: &ForeignItemFn,
+    abi: Abi,
+) -> Result<Generated> {
+    if let Some(variadic) = &primal.sig.variadic {
+        return Err(Error::new_spanned(
+            variadic,
+            "Variadic functions can't be differentiated!",
+        ));
+    }
+    // The body is never emitted, it only lets us reuse the checks and passes for functions.
+    // Foreign functions are unsafe to call, e.g. when their pointer type is checked for callbacks.
+    let ForeignItemFn { vis, sig, .. } = primal;
+    let as_fn: ItemFn = parse_quote! { #(#attrs)* #vis unsafe #abi #sig {} };
+    let mut generated = generate(config, &as_fn)?;
+    // The backend differentiates the foreign function itself.
+    generated.shim = None;
+    let name = &generated.declaration.sig.ident;
+    if generated.wrapper.is_some() {
+        return Err(Error::new(
+            name.span(),
+            format!("`{name}` would need a wrapper, which foreign functions don't support! Arrays by value, containers, `layout = array` and runtime widths aren't supported for them."),
+        ));
+    }
+    Ok(generated)
+}
+
+#[doc(hidden)]
+fn adjust_name(new_name: syn::Ident, fnc: &mut ForeignItemFn) {
+    // Collisions are already reported by check_collisions.
+    fnc.sig.ident = new_name;
+}
+
+#[doc(hidden)]
+pub(crate) fn adjust_parameters(
+    input: DiffConfig,
+    fnc: &mut ForeignItemFn,
+) -> Result<Option<syn::ItemStruct>> {
+    match input {
+        DiffConfig::Fwd(f) => forward::adjust_parameters(f, fnc),
+        DiffConfig::Rev(r) => reverse::adjust_parameters(r, fnc),
+    }
+}
@@ -0,0 +1,51 @@
+//! Metadata which we emit next to each declaration.
+//!
+//! Some settings can't be expressed by the signature of the derivative alone,
+//! e.g. whether the primal runs in a parallel context.
+//! For each derivative we therefore emit a `#[no_mangle]` static named `__enzyme_meta_<name>`,
+//! which holds a nul-terminated list of `key=value` lines that the backend reads.
+//! If a symbol prefix is set, it's exported as `<prefix>__enzyme_meta_<name>` instead.
+
+use proc_macro2::Span;
+use syn::{parse_quote, Ident, LitByteStr};
+
+#[doc(hidden)]
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Metadata {
+    entries: Vec<(String, String)>,
+}
+
+impl Metadata {
+    pub(crate) fn push(&mut self, key: &str, value: impl ToString) {
+        self.entries.push((key.to_owned(), value.to_string()));
+    }
+
+    /// Renders the entries into the byte string stored in the static.
+    pub(crate) fn render(&self) -> String {
+        self.entries
+            .iter()
+            .map(|(key, value)| format!("{key}={value}\n"))
+            .collect()
+    }
+
+    pub(crate) fn to_static(&self, grad_name: &Ident, prefix: &str) -> syn::ItemStatic {
+        let ident = Ident::new(&format!("__enzyme_meta_{grad_name}"), grad_name.span());
+        self.to_static_named(ident, prefix)
+    }
+
+    /// Like `to_static`, for metadata which doesn't belong to a derivative.
+    pub(crate) fn to_static_named(&self, ident: Ident, prefix: &str) -> syn::ItemStatic {
+        let mut bytes = self.render().into_bytes();
+        bytes.push(0);
+        let len = bytes.len();
+        let lit = LitByteStr::new(&bytes, Span::call_site());
+        let export = crate::export_attr(&ident, prefix);
+        parse_quote! {
+            #[doc(hidden)]
+            #[allow(non_upper_case_globals)]
+            #[used]
+            #export
+            static #ident: [u8; #len] = *#lit;
+        }
+    }
+}
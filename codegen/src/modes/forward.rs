@@ -0,0 +1,574 @@
+//! The forward-mode Interface
+//!
+//! It is
+use proc_macro2::{Span, TokenStream};
+use quote::{quote, ToTokens};
+use std::fmt;
+use syn::parse::Parse;
+use syn::punctuated::Punctuated;
+use syn::{parenthesized, Error, FnArg, ForeignItemFn, Ident, ItemFn, Signature, Type};
+use syn::{parse::ParseStream, Token};
+
+use crate::helper::{create_ret_struct, hide_declaration, Hidden};
+use crate::types::{self, DiffConfig, Layout, Options, Width};
+
+use super::reverse::ReturnActivity;
+use super::{
+    check_activity_count, fmt_granularity, infer_activities, make_field, parse_overrides,
+    tangent_shadow, Tangents, TypeClass,
+};
+
+//
+// Here we define some types relevant for forward-mode AD
+//
+
+/// Forward-Mode uses a more restricted version of the general Activity enum.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FwdActivity {
+    /// We expect
+    Duplicated,
+    Constant,
+}
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FwdReturnActivity {
+    Active,   // return primary ret + gradient
+    Gradient, // return gradient only
+}
+#[doc(hidden)]
+#[non_exhaustive]
+#[derive(Debug, Clone)]
+pub enum FwdGranularity {
+    All(FwdActivity),
+    PerInput(Vec<FwdActivity>),
+    /// Infer the activity from the parameter type, with optional overrides per parameter name.
+    /// f32/f64 values and references or pointers to them become Duplicated,
+    /// integers and bools Constant.
+    Auto(Vec<(Ident, FwdActivity)>),
+    //PerScalar(..),
+}
+#[derive(Clone)]
+pub struct FwdInfo {
+    /// None until resolved, if the user didn't give a name.
+    pub grad_fnc_name: Option<Ident>,
+    /// Ignored if the width is only known at runtime.
+    pub width: Width,
+    pub runtime_width: Option<RuntimeWidth>,
+    pub input_activity: FwdGranularity,
+    pub return_activity: FwdReturnActivity,
+    pub options: Options,
+}
+
+/// A vector width which isn't known while expanding the macro.
+#[derive(Debug, Clone)]
+pub enum RuntimeWidth {
+    /// `Forward(WIDTH)` with a `const WIDTH: usize` item. The tangents are passed as arrays.
+    Const(syn::Path),
+    /// `Forward(dyn)`, the caller passes each shadow as a pointer to `width` tangents.
+    Dyn,
+}
+
+//
+// Here we define the key functions to generate the declaration of our derivative function,
+// as well as the struct it will return.
+//
+
+#[doc(hidden)]
+pub(crate) fn adjust_parameters(
+    infos: FwdInfo,
+    fnc: &mut syn::ForeignItemFn,
+) -> syn::Result<Option<syn::ItemStruct>> {
+    if infos.runtime_width.is_some() {
+        return adjust_parameters_runtime(infos, fnc);
+    }
+    // First, we need to create <width> copies of each active input
+
+    let mut new_params: Punctuated<syn::FnArg, syn::token::Comma> = Punctuated::new();
+
+    let activities = resolve_activities(&infos.input_activity, fnc)?;
+    let params = &fnc.sig.inputs;
+
+    for (&act, param) in activities.iter().zip(params.iter()) {
+        handle_input_params_fwd(
+            infos.width,
+            act,
+            &Tangents::new(&infos.options),
+            param.clone(),
+            &mut new_params,
+        )?;
+    }
+    fnc.sig.inputs = new_params;
+
+    // Second, we need to create <width> copies of the output and (optionally) use the primary output too.
+    adjust_output_parameters(infos, fnc)
+}
+
+/// One activity per input parameter, no matter how the user specified them.
+///
+/// For `Auto`, the inferred activities are documented on `fnc`.
+#[doc(hidden)]
+pub(crate) fn resolve_activities(
+    granularity: &FwdGranularity,
+    fnc: &mut ForeignItemFn,
+) -> syn::Result<Vec<FwdActivity>> {
+    let activities = match granularity {
+        FwdGranularity::All(activity) => vec![*activity; fnc.sig.inputs.len()],
+        FwdGranularity::PerInput(activities) => {
+            check_activity_count(fnc, activities.len())?;
+            activities.clone()
+        }
+        FwdGranularity::Auto(overrides) => infer_activities(fnc, overrides, |class| match class {
+            TypeClass::Float | TypeClass::FloatPointer => Some(FwdActivity::Duplicated),
+            TypeClass::Integral => Some(FwdActivity::Constant),
+            TypeClass::Unknown => None,
+        })?,
+    };
+    Ok(activities)
+}
+
+#[doc(hidden)]
+pub(crate) fn adjust_output_parameters(
+    infos: FwdInfo,
+    fnc: &mut ForeignItemFn,
+) -> syn::Result<Option<syn::ItemStruct>> {
+    let ret_act = infos.return_activity;
+    let mut new_ret_struct =
+        create_ret_struct(types::DiffConfig::Fwd(infos.clone()), fnc.sig.clone());
+    // 4.a Add the gradient of the primary return, if appropriate
+    if ret_act == FwdReturnActivity::Active || ret_act == FwdReturnActivity::Gradient {
+        let prev_ret = match &fnc.sig.output {
+            syn::ReturnType::Default => {
+                return Err(Error::new(
+                    fnc.sig.ident.span(),
+                    "Your function returns (), so please don't specify a return activity!",
+                ));
+            }
+            syn::ReturnType::Type(_, inner) => *inner.clone(),
+        };
+
+        if let syn::Fields::Named(ref mut inner) = new_ret_struct.fields {
+            let grad_name = "primary_grad".to_owned();
+            let width_u32 = u32::from(infos.width);
+            if infos.options.layout == Layout::Array || infos.runtime_width.is_some() {
+                let width = width_expr(&infos);
+                inner.named.push(make_field(
+                    syn::parse_quote! { [#prev_ret; #width] },
+                    grad_name,
+                ));
+            } else if width_u32 == 1 {
+                inner.named.push(make_field(prev_ret, grad_name));
+            } else {
+                // Forward-Mode-Vector
+                for i in 0..width_u32 {
+                    inner.named.push(make_field(
+                        prev_ret.clone(),
+                        grad_name.clone() + &i.to_string(),
+                    ));
+                }
+            }
+        } else {
+            unreachable!();
+        }
+    }
+    Ok(Some(new_ret_struct))
+}
+
+/// The vector width as expression, for array types.
+fn width_expr(infos: &FwdInfo) -> TokenStream {
+    match &infos.runtime_width {
+        Some(RuntimeWidth::Const(path)) => quote! { #path },
+        _ => {
+            let lit = syn::LitInt::new(&format!("{}usize", infos.width), Span::call_site());
+            quote! { #lit }
+        }
+    }
+}
+
+/// The type of the array holding all tangents of a parameter of type `ty`.
+fn tangent_array(ty: &Type, width: &TokenStream) -> Type {
+    match ty {
+        Type::Reference(r) => {
+            let mut r = r.clone();
+            let elem = &r.elem;
+            r.elem = syn::parse_quote! { [#elem; #width] };
+            Type::Reference(r)
+        }
+        Type::Ptr(p) => {
+            let mut p = p.clone();
+            let elem = &p.elem;
+            p.elem = syn::parse_quote! { [#elem; #width] };
+            Type::Ptr(p)
+        }
+        ty => syn::parse_quote! { [#ty; #width] },
+    }
+}
+
+/// The type of the pointer to all tangents of a parameter of type `ty`, for runtime widths.
+fn tangent_pointer(ty: &Type) -> Type {
+    match ty {
+        Type::Reference(r) if r.mutability.is_some() => {
+            let elem = &r.elem;
+            syn::parse_quote! { *mut #elem }
+        }
+        Type::Reference(r) => {
+            let elem = &r.elem;
+            syn::parse_quote! { *const #elem }
+        }
+        Type::Ptr(p) => Type::Ptr(p.clone()),
+        ty => syn::parse_quote! { *const #ty },
+    }
+}
+
+fn param_ident(param: &FnArg) -> syn::Result<(&syn::PatType, Ident)> {
+    let pat_ty = match param {
+        FnArg::Typed(pat_ty) => pat_ty,
+        FnArg::Receiver(r) => return Err(Error::new_spanned(r, "self not supported!")),
+    };
+    match &*pat_ty.pat {
+        syn::Pat::Ident(pat_ident) => Ok((pat_ty, pat_ident.ident.clone())),
+        pat => Err(Error::new_spanned(
+            pat,
+            "Please use a plain parameter name!",
+        )),
+    }
+}
+
+/// For runtime widths, each shadow is passed as a pointer to `width` tangents,
+/// and the tangents of the return value are written to `d_ret`.
+#[doc(hidden)]
+fn adjust_parameters_runtime(
+    infos: FwdInfo,
+    fnc: &mut ForeignItemFn,
+) -> syn::Result<Option<syn::ItemStruct>> {
+    let activities = resolve_activities(&infos.input_activity, fnc)?;
+    let mut new_params: Punctuated<FnArg, syn::token::Comma> = Punctuated::new();
+    for (&act, param) in activities.iter().zip(fnc.sig.inputs.iter()) {
+        new_params.push(param.clone());
+        if act == FwdActivity::Constant {
+            continue;
+        }
+        let (pat_ty, name) = param_ident(param)?;
+        let shadow = Ident::new(&format!("d_{name}"), name.span());
+        let mut ty = (*pat_ty.ty).clone();
+        tangent_shadow(&mut ty, &Tangents::new(&infos.options));
+        let ty = tangent_pointer(&ty);
+        new_params.push(syn::parse_quote! { #shadow: #ty });
+    }
+    let ret_ty = match &fnc.sig.output {
+        syn::ReturnType::Default => {
+            return Err(Error::new(
+                fnc.sig.ident.span(),
+                "Your function returns (), so please don't specify a return activity!",
+            ));
+        }
+        syn::ReturnType::Type(_, ty) => ty.clone(),
+    };
+    new_params.push(syn::parse_quote! { d_ret: *mut #ret_ty });
+    new_params.push(syn::parse_quote! { width: usize });
+
+    // Const widths get a wrapper which returns the tangents in an array, see `wrapper`.
+    let ret_struct = match infos.runtime_width {
+        Some(RuntimeWidth::Const(_)) => adjust_output_parameters(infos.clone(), &mut fnc.clone())?,
+        _ => None,
+    };
+    fnc.sig.inputs = new_params;
+    if infos.return_activity == FwdReturnActivity::Gradient {
+        fnc.sig.output = syn::ReturnType::Default;
+    }
+    Ok(ret_struct)
+}
+
+/// Some variants of vector forward mode pass the tangents of each input in one array,
+/// `layout = array` and `Forward(WIDTH)` for a const `WIDTH`.
+/// We then turn the declaration into a hidden raw declaration with the ABI which Enzyme expects,
+/// and return a wrapper which takes one array of tangents per input.
+///
+/// For literal widths the raw declaration takes one parameter per tangent direction
+/// and the wrapper splits up the arrays, the return type stays the one of the declaration.
+/// For const widths the raw declaration takes pointers to the tangents, like `Forward(dyn)`.
+#[doc(hidden)]
+pub(crate) fn wrapper(
+    infos: &FwdInfo,
+    primal: &Signature,
+    decl: &mut ForeignItemFn,
+) -> syn::Result<ItemFn> {
+    let width = width_expr(infos);
+    let mut scratch = decl.clone();
+    scratch.sig.inputs = primal.inputs.clone();
+    let activities = resolve_activities(&infos.input_activity, &mut scratch)?;
+    let const_width = matches!(infos.runtime_width, Some(RuntimeWidth::Const(_)));
+
+    let mut inputs: Punctuated<FnArg, syn::token::Comma> = Punctuated::new();
+    let mut splits = vec![];
+    let mut args = vec![];
+    for (&act, param) in activities.iter().zip(primal.inputs.iter()) {
+        let (pat_ty, name) = param_ident(param)?;
+        inputs.push(param.clone());
+        args.push(quote! { #name });
+        if act == FwdActivity::Constant {
+            continue;
+        }
+        let shadow = Ident::new(&format!("d_{name}"), name.span());
+        let mut shadow_ty = (*pat_ty.ty).clone();
+        tangent_shadow(&mut shadow_ty, &Tangents::new(&infos.options));
+        let ty = tangent_array(&shadow_ty, &width);
+        inputs.push(syn::parse_quote! { #shadow: #ty });
+        if const_width {
+            args.push(match &shadow_ty {
+                Type::Ptr(ptr) => {
+                    let elem = &ptr.elem;
+                    quote! { #shadow.cast::<#elem>() }
+                }
+                Type::Reference(r) if r.mutability.is_some() => quote! { #shadow.as_mut_ptr() },
+                _ => quote! { #shadow.as_ptr() },
+            });
+            continue;
+        }
+        let tangents: Vec<Ident> = (0..u32::from(infos.width))
+            .map(|i| Ident::new(&format!("d_{name}_{i}"), name.span()))
+            .collect();
+        splits.push(match &shadow_ty {
+            // A raw pointer to the array, so we compute a pointer to each element.
+            Type::Ptr(ptr) => {
+                let elem = &ptr.elem;
+                let idx = 0..tangents.len();
+                quote! { #(let #tangents = #shadow.cast::<#elem>().add(#idx);)* }
+            }
+            // Values are moved out of the array, references borrow from it.
+            _ => quote! { let [#(#tangents),*] = #shadow; },
+        });
+        args.extend(tangents.iter().map(|t| quote! { #t }));
+    }
+
+    let Hidden {
+        raw,
+        docs,
+        vis,
+        mut sig,
+    } = hide_declaration(decl);
+    sig.inputs = inputs;
+    let call = quote! { #raw(#(#args),*) };
+    let body = match (const_width, &primal.output) {
+        (true, syn::ReturnType::Type(_, ret_ty)) => {
+            let ret_ident = DiffConfig::Fwd(infos.clone()).ret_name();
+            sig.output = syn::parse_quote! { -> #ret_ident };
+            let tangents = quote! {
+                let mut d_ret = ::core::mem::MaybeUninit::<[#ret_ty; #width]>::uninit();
+            };
+            let call = quote! { #raw(#(#args,)* d_ret.as_mut_ptr().cast::<#ret_ty>(), #width) };
+            match infos.return_activity {
+                FwdReturnActivity::Active => quote! {
+                    #tangents
+                    let primary_ret = #call;
+                    #ret_ident { primary_ret, primary_grad: d_ret.assume_init() }
+                },
+                FwdReturnActivity::Gradient => quote! {
+                    #tangents
+                    #call;
+                    #ret_ident { primary_grad: d_ret.assume_init() }
+                },
+            }
+        }
+        _ => quote! {
+            #(#splits)*
+            #call
+        },
+    };
+    Ok(syn::parse_quote! {
+        #(#docs)*
+        #[inline]
+        #vis #sig {
+            unsafe {
+                #body
+            }
+        }
+    })
+}
+
+#[doc(hidden)]
+fn handle_input_params_fwd(
+    width: Width,
+    act: FwdActivity,
+    tangents: &Tangents,
+    param: syn::FnArg,
+    inputs: &mut Punctuated<FnArg, syn::token::Comma>,
+) -> syn::Result<()> {
+    // No matter what, we always keep the primary:
+    inputs.push(param.clone());
+
+    if let FwdActivity::Constant = act {
+        return Ok(()); // We don't duplicate constant inputs
+    } // else is always FwdActivity::Duplicated
+
+    let u32_width = u32::from(width);
+    let usize_width: usize = u32_width.try_into().unwrap();
+    let params = vec![param; usize_width];
+
+    for (i, mut param) in params.into_iter().enumerate() {
+        // There is no reasonable way to differentiate methods containing self.
+        let pat_ty = match param {
+            FnArg::Typed(ref mut pat_ty) => pat_ty,
+            FnArg::Receiver(r) => return Err(Error::new_spanned(r, "self not supported!")),
+        };
+
+        // Unlike in the reverse pass, we won't modify inputs during runtime.
+        // So we don't require mutability of inputs here.
+        tangent_shadow(&mut pat_ty.ty, tangents);
+
+        if let syn::Pat::Ident(ref mut pat_ident) = *pat_ty.pat {
+            let mut base_name = pat_ident.ident.to_string();
+            base_name = format!("d_{base_name}");
+            let pat_span = pat_ident.ident.span();
+
+            let input_name = format!("{base_name}_{i}");
+            pat_ident.ident = syn::Ident::new(&input_name, pat_span);
+            inputs.push(param);
+        } else {
+            unreachable!("implementation error")
+        }
+    }
+    Ok(())
+}
+
+// Re-implementation (I guess due to missing Specification)
+// to only allow Const / Duplicated as (Return)Activity
+// Should give better user error messages compared to later catching.
+
+impl Parse for FwdActivity {
+    fn parse(input: ParseStream) -> Result<Self, syn::Error> {
+        let ident: Ident = input.parse()?;
+        match ident.to_string().as_str() {
+            "Constant" => Ok(FwdActivity::Constant),
+            "Duplicated" => Ok(FwdActivity::Duplicated),
+            _ => Err(Error::new(
+                ident.span(),
+                "Forward Mode AD only supports Duplicated and Constant here!",
+            )),
+        }
+    }
+}
+
+impl From<FwdReturnActivity> for ReturnActivity {
+    fn from(f: FwdReturnActivity) -> Self {
+        match f {
+            FwdReturnActivity::Active => ReturnActivity::Active,
+            FwdReturnActivity::Gradient => ReturnActivity::Gradient,
+        }
+    }
+}
+
+impl Parse for FwdReturnActivity {
+    fn parse(input: ParseStream) -> Result<Self, syn::Error> {
+        let ident: Ident = input.parse()?;
+        let out = match ident.to_string().as_str() {
+            "Active" => FwdReturnActivity::Active,
+            "Gradient" => FwdReturnActivity::Gradient,
+            _ => {
+                return Err(Error::new(
+                    ident.span(),
+                    "Failed parsing return activity. Please use Active or Gradient!",
+                ))
+            }
+        };
+        Ok(out)
+    }
+}
+
+impl Parse for FwdGranularity {
+    fn parse(input: ParseStream) -> Result<Self, syn::Error> {
+        let category: Ident = input.parse()?;
+        if category == "Auto" {
+            return Ok(FwdGranularity::Auto(parse_overrides(input)?));
+        }
+
+        let content;
+        let _paren_token = parenthesized!(content in input);
+        let activities: Punctuated<FwdActivity, Token![,]> =
+            content.parse_terminated(FwdActivity::parse)?;
+        let activities: Vec<FwdActivity> = activities.into_iter().collect();
+        match category.to_string().as_str() {
+            "All" if activities.len() == 1 => Ok(FwdGranularity::All(activities[0])),
+            "All" => Err(Error::new(
+                category.span(),
+                "All expects exactly one activity!",
+            )),
+            "PerInput" => Ok(FwdGranularity::PerInput(activities)),
+            _ => Err(Error::new(
+                category.span(),
+                format!("Expected All, PerInput or Auto. Got {category}"),
+            )),
+        }
+    }
+}
+
+impl fmt::Display for FwdInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let u32_width = u32::from(self.width);
+        let mode = if let Some(width) = &self.runtime_width {
+            format!("fwd-mode-vector({width})")
+        } else if u32_width > 1 {
+            format!("fwd-mode-vector({u32_width})")
+        } else {
+            "fwd-mode".to_owned()
+        };
+        let name = self
+            .grad_fnc_name
+            .as_ref()
+            .map_or("<unnamed>".to_owned(), |n| n.to_string());
+        let input = &self.input_activity;
+        let ret = self.return_activity;
+        let output = format!(
+            "handling {name}\nusing {mode}\nwith input activity {input}\nwith output activity {ret:?}"
+        );
+        write!(f, "{output}")
+    }
+}
+impl fmt::Display for RuntimeWidth {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RuntimeWidth::Const(path) => {
+                let path = path.to_token_stream().to_string().replace(' ', "");
+                write!(f, "{path}")
+            }
+            RuntimeWidth::Dyn => write!(f, "dyn"),
+        }
+    }
+}
+impl fmt::Display for FwdGranularity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FwdGranularity::All(act) => fmt_granularity(f, "All", &[*act], &[]),
+            FwdGranularity::PerInput(acts) => fmt_granularity(f, "PerInput", acts, &[]),
+            FwdGranularity::Auto(overrides) => fmt_granularity(f, "Auto", &[], overrides),
+        }
+    }
+}
+
+#[doc(hidden)]
+pub(crate) fn parse(
+    grad_fnc_name: Option<proc_macro2::Ident>,
+    input: ParseStream,
+    width: Width,
+    runtime_width: Option<RuntimeWidth>,
+) -> Result<DiffConfig, syn::Error> {
+    let granularity: FwdGranularity = input.parse()?;
+    let _: Token![,] = input.parse()?;
+    let return_activity: FwdReturnActivity = input.parse()?;
+    let options_span = input.span();
+    let options = Options::parse_trailing(input)?;
+    if !options.checkpoint.is_empty() {
+        return Err(Error::new(
+            options_span,
+            "The checkpoint option only applies to reverse mode, forward mode doesn't cache anything!",
+        ));
+    }
+
+    let res = types::DiffConfig::Fwd(FwdInfo {
+        grad_fnc_name,
+        width,
+        runtime_width,
+        input_activity: granularity,
+        return_activity,
+        options,
+    });
+    Ok(res)
+}
@@ -0,0 +1,258 @@
+//! An overview of the AD modes which we support
+#[doc(hidden)]
+pub mod forward;
+pub mod reverse;
+
+use std::fmt::Debug;
+
+#[doc(hidden)]
+pub use forward as FwdMode;
+use quote::ToTokens;
+#[doc(hidden)]
+pub use reverse as RevMode;
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
+use syn::{Attribute, Error, Field, FnArg, Ident, Token, Type, Visibility};
+
+#[doc(hidden)]
+fn make_field(ty: syn::Type, arg_name: String) -> syn::Field {
+    Field {
+        attrs: vec![],
+        vis: Visibility::Inherited,
+        ident: Some(Ident::new(&arg_name, proc_macro2::Span::mixed_site())),
+        colon_token: Some(Default::default()),
+        ty,
+    }
+}
+
+#[doc(hidden)]
+fn check_activity_count(fnc: &syn::ForeignItemFn, count: usize) -> syn::Result<()> {
+    if fnc.sig.inputs.len() != count {
+        return Err(Error::new(
+            fnc.sig.ident.span(),
+            format!(
+                "Please provide one activity value per input parameter! Expected {}, got {count}.",
+                fnc.sig.inputs.len()
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Shared Display implementation of the granularities of both modes.
+#[doc(hidden)]
+fn fmt_granularity<A: Debug>(
+    f: &mut std::fmt::Formatter,
+    category: &str,
+    activities: &[A],
+    overrides: &[(Ident, A)],
+) -> std::fmt::Result {
+    let inner: Vec<String> = match category {
+        "Auto" => overrides
+            .iter()
+            .map(|(name, act)| format!("{name} = {act:?}"))
+            .collect(),
+        _ => activities.iter().map(|act| format!("{act:?}")).collect(),
+    };
+    match (category, inner.is_empty()) {
+        ("Auto", true) => write!(f, "Auto"),
+        _ => write!(f, "{category}({})", inner.join(", ")),
+    }
+}
+
+#[doc(hidden)]
+fn make_doc(text: &str) -> Attribute {
+    syn::parse_quote! { #[doc = #text] }
+}
+
+/// The rough category of a parameter type, as far as `Auto` activity inference is concerned.
+#[doc(hidden)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum TypeClass {
+    /// f32 or f64, passed by value.
+    Float,
+    /// A reference or pointer to f32/f64 data, including arrays, slices and containers of them.
+    FloatPointer,
+    /// Integers, bools and chars, passed by value or behind a reference or pointer.
+    Integral,
+    /// Anything else. We don't guess here.
+    Unknown,
+}
+
+fn is_float(ty: &Type) -> bool {
+    match ty {
+        Type::Path(p) => p.qself.is_none() && (p.path.is_ident("f32") || p.path.is_ident("f64")),
+        Type::Paren(p) => is_float(&p.elem),
+        Type::Group(g) => is_float(&g.elem),
+        _ => false,
+    }
+}
+
+fn is_integral(ty: &Type) -> bool {
+    const INTEGRAL: [&str; 14] = [
+        "i8", "i16", "i32", "i64", "i128", "isize", "u8", "u16", "u32", "u64", "u128", "usize",
+        "bool", "char",
+    ];
+    match ty {
+        Type::Path(p) => p.qself.is_none() && INTEGRAL.iter().any(|&name| p.path.is_ident(name)),
+        Type::Paren(p) => is_integral(&p.elem),
+        Type::Group(g) => is_integral(&g.elem),
+        _ => false,
+    }
+}
+
+/// Classify a parameter type for the activity inference of `Auto`.
+#[doc(hidden)]
+pub(crate) fn classify(ty: &Type) -> TypeClass {
+    let pointee = match ty {
+        Type::Reference(r) => &*r.elem,
+        Type::Ptr(p) => &*p.elem,
+        Type::Paren(p) => return classify(&p.elem),
+        Type::Group(g) => return classify(&g.elem),
+        _ if is_float(ty) => return TypeClass::Float,
+        _ if is_integral(ty) => return TypeClass::Integral,
+        _ => return TypeClass::Unknown,
+    };
+    // Containers like `Vec<f64>` are classified by their elements as well.
+    let buffer_elem = crate::buffers::buffer_elem(pointee, &[]);
+    let elem = match (pointee, &buffer_elem) {
+        (_, Some(elem)) => elem,
+        (Type::Array(a), _) => &*a.elem,
+        (Type::Slice(s), _) => &*s.elem,
+        (other, _) => other,
+    };
+    if is_float(elem) {
+        TypeClass::FloatPointer
+    } else if is_integral(elem) {
+        TypeClass::Integral
+    } else {
+        TypeClass::Unknown
+    }
+}
+
+/// Decides which shadows are typed as the tangent of their primal type, see the `tangent` option.
+#[doc(hidden)]
+pub(crate) struct Tangents {
+    /// The types listed in the `tangent` option.
+    listed: Vec<syn::Path>,
+}
+
+impl Tangents {
+    pub(crate) fn new(options: &crate::Options) -> Self {
+        Tangents {
+            listed: options.tangent.clone(),
+        }
+    }
+
+    /// Only listed types count. Whether a type derives `Differentiable` isn't known while
+    /// expanding, and other types keep working with plain shadows of their own type.
+    fn applies(&self, ty: &Type) -> bool {
+        match ty {
+            Type::Path(p) if p.qself.is_none() => self.listed.contains(&p.path),
+            _ => false,
+        }
+    }
+}
+
+/// Points a shadow to the tangent of its primal type, e.g. `&mut Model` to `&mut ModelTangent`.
+/// Arrays and slices of such types are handled as well.
+#[doc(hidden)]
+pub(crate) fn tangent_shadow(ty: &mut Type, tangents: &Tangents) {
+    let elem = match ty {
+        Type::Reference(r) => &mut *r.elem,
+        Type::Ptr(p) => &mut *p.elem,
+        Type::Paren(p) => return tangent_shadow(&mut p.elem, tangents),
+        Type::Group(g) => return tangent_shadow(&mut g.elem, tangents),
+        _ => return,
+    };
+    to_tangent(elem, tangents);
+}
+
+fn to_tangent(ty: &mut Type, tangents: &Tangents) {
+    if tangents.applies(ty) {
+        *ty = syn::parse_quote! { <#ty as ::autodiff_runtime::Differentiable>::Tangent };
+        return;
+    }
+    match ty {
+        Type::Array(a) => to_tangent(&mut a.elem, tangents),
+        Type::Slice(s) => to_tangent(&mut s.elem, tangents),
+        Type::Paren(p) => to_tangent(&mut p.elem, tangents),
+        Type::Group(g) => to_tangent(&mut g.elem, tangents),
+        _ => {}
+    }
+}
+
+/// Parses the optional overrides of `Auto`, e.g. `Auto(x = Constant, y = Active)`.
+#[doc(hidden)]
+pub(crate) fn parse_overrides<A: Parse>(input: ParseStream) -> syn::Result<Vec<(Ident, A)>> {
+    if !input.peek(syn::token::Paren) {
+        return Ok(vec![]);
+    }
+    let content;
+    let _paren_token = syn::parenthesized!(content in input);
+    let overrides: Punctuated<(Ident, A), Token![,]> =
+        content.parse_terminated(|input: ParseStream| {
+            let name: Ident = input.parse()?;
+            let _: Token![=] = input.parse()?;
+            let act: A = input.parse()?;
+            Ok((name, act))
+        })?;
+    Ok(overrides.into_iter().collect())
+}
+
+/// Infer one activity per parameter from its type, unless the user gave an override for it.
+///
+/// The chosen activities are documented on the generated declaration,
+/// so they can be reviewed in the docs of the crate.
+#[doc(hidden)]
+pub(crate) fn infer_activities<A: Copy + Debug>(
+    fnc: &mut syn::ForeignItemFn,
+    overrides: &[(Ident, A)],
+    infer: impl Fn(TypeClass) -> Option<A>,
+) -> syn::Result<Vec<A>> {
+    let mut param_names = vec![];
+    let mut activities = vec![];
+    let mut docs = vec![make_doc(" Activities inferred by `Auto`:"), make_doc("")];
+    for param in fnc.sig.inputs.iter() {
+        let pat_ty = match param {
+            FnArg::Typed(pat_ty) => pat_ty,
+            FnArg::Receiver(r) => return Err(Error::new_spanned(r, "self not supported!")),
+        };
+        let name = match &*pat_ty.pat {
+            syn::Pat::Ident(pat_ident) => pat_ident.ident.clone(),
+            pat => {
+                return Err(Error::new_spanned(
+                    pat,
+                    "Auto requires plain identifiers as parameter names!",
+                ))
+            }
+        };
+        let ty_str = pat_ty.ty.to_token_stream().to_string();
+        let (act, reason) = match overrides.iter().find(|(id, _)| *id == name) {
+            Some((_, act)) => (*act, "override"),
+            None => match infer(classify(&pat_ty.ty)) {
+                Some(act) => (act, "inferred"),
+                None => return Err(Error::new_spanned(
+                    &pat_ty.ty,
+                    format!("Can't infer the activity of `{name}: {ty_str}`, please add an override like Auto({name} = Constant)!"),
+                )),
+            },
+        };
+        docs.push(make_doc(&format!(
+            " - `{name}: {ty_str}`: {act:?} ({reason})"
+        )));
+        param_names.push(name);
+        activities.push(act);
+    }
+    if let Some((name, _)) = overrides
+        .iter()
+        .find(|(name, _)| !param_names.contains(name))
+    {
+        return Err(Error::new(
+            name.span(),
+            format!("Auto override for `{name}` doesn't match any parameter!"),
+        ));
+    }
+    fnc.attrs.extend(docs);
+    Ok(activities)
+}
@@ -0,0 +1,457 @@
+//! The reverse-mode Interface
+
+use std::fmt;
+
+use quote::ToTokens;
+use syn::{
+    parenthesized, parse::Parse, parse_quote, punctuated::Punctuated, Error, FnArg, ForeignItemFn,
+    Ident, LitBool, PathArguments, PathSegment, ReturnType, Token, Type,
+};
+
+use crate::{
+    helper::create_ret_struct,
+    types::{self, DiffConfig, Layout, Options},
+};
+use syn::parse::ParseStream;
+
+use super::{
+    check_activity_count, fmt_granularity, infer_activities, make_field, parse_overrides,
+    tangent_shadow, Tangents, TypeClass,
+};
+
+#[derive(Clone)]
+pub struct RevInfo {
+    /// None until resolved, if the user didn't give a name.
+    pub grad_fnc_name: Option<Ident>,
+    pub input_activity: Granularity,
+    pub return_activity: ReturnActivity,
+    pub parallel_context: bool,
+    pub options: Options,
+}
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Activity {
+    /// The gradient of this input f32/f64 value will be added to the return struct.
+    /// The input f32/f64 value will be duplicated, the second parameter will be treated as a
+    /// scalar factor.
+    Active,
+    /// This primal input parameter will be duplicated by adding a shaddow variable.
+    /// Enzyme will add \partialf / \partialx to the
+    /// shaddow, so you usually want to initialize your shaddow to zero.
+    Duplicated,
+    /// Similar to Duplicated. However, the primal value will be dropped and can't be used after
+    /// calling this function. This might allow extra optimizations in some cases.
+    Gradient,
+    /// Enzyme will not differente in respect to Constant inputs.
+    Constant,
+}
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ReturnActivity {
+    /// return primary ret + gradient
+    Active,
+    /// return gradient only
+    Gradient,
+    /// return primary  only
+    Constant,
+    /// return neither
+    Ignore,
+    /// primary has no return
+    None,
+}
+#[non_exhaustive]
+#[derive(Debug, Clone)]
+pub enum Granularity {
+    All(Activity),
+    PerInput(Vec<Activity>),
+    /// Infer the activity from the parameter type, with optional overrides per parameter name.
+    /// f32/f64 values become Active, references and pointers to them Duplicated,
+    /// integers and bools Constant.
+    Auto(Vec<(Ident, Activity)>),
+    //PerScalar(..),
+}
+
+#[doc(hidden)]
+pub(crate) fn parse(
+    grad_fnc_name: Option<proc_macro2::Ident>,
+    input: ParseStream,
+) -> Result<DiffConfig, syn::Error> {
+    let input_activity: Granularity = input.parse()?;
+    let _: Token![,] = input.parse()?;
+    let return_activity: ReturnActivity = input.parse()?;
+    // The parallel context can be omitted, most primals don't run in one.
+    let parallel_context = match input.peek(Token![,]) && input.peek2(LitBool) {
+        true => {
+            let _: Token![,] = input.parse()?;
+            input.parse::<LitBool>()?.value
+        }
+        false => false,
+    };
+    let options_span = input.span();
+    let options = Options::parse_trailing(input)?;
+    if options.layout != Layout::Fields {
+        return Err(Error::new(
+            options_span,
+            "The layout option only applies to vector forward mode!",
+        ));
+    }
+    let res = DiffConfig::Rev(RevInfo {
+        grad_fnc_name,
+        input_activity,
+        return_activity,
+        parallel_context,
+        options,
+    });
+    Ok(res)
+}
+
+#[doc(hidden)]
+pub(crate) fn adjust_parameters(
+    input: RevInfo,
+    fnc: &mut syn::ForeignItemFn,
+) -> syn::Result<Option<syn::ItemStruct>> {
+    let out_changes = adjust_input_parameters(
+        input.input_activity.clone(),
+        input.parallel_context,
+        &Tangents::new(&input.options),
+        fnc,
+    )?;
+    adjust_output_parameters(out_changes, input, fnc)
+}
+
+/// In a parallel context several threads might add to the same shadow at the same time,
+/// so we replace f32/f64 shadows by atomics of the same size and layout.
+/// Returns None for types which can't be accumulated atomically.
+#[doc(hidden)]
+fn atomic_shadow(elem: &Type) -> Option<Type> {
+    match elem {
+        Type::Path(p) if p.path.is_ident("f64") => {
+            Some(parse_quote! { ::core::sync::atomic::AtomicU64 })
+        }
+        Type::Path(p) if p.path.is_ident("f32") => {
+            Some(parse_quote! { ::core::sync::atomic::AtomicU32 })
+        }
+        Type::Array(arr) => {
+            let mut arr = arr.clone();
+            *arr.elem = atomic_shadow(&arr.elem)?;
+            Some(Type::Array(arr))
+        }
+        Type::Slice(slice) => {
+            let mut slice = slice.clone();
+            *slice.elem = atomic_shadow(&slice.elem)?;
+            Some(Type::Slice(slice))
+        }
+        Type::Paren(p) => atomic_shadow(&p.elem),
+        Type::Group(g) => atomic_shadow(&g.elem),
+        _ => None,
+    }
+}
+
+#[doc(hidden)]
+fn handle_param_rev(
+    act: Activity,
+    parallel_context: bool,
+    tangents: &Tangents,
+    param: syn::FnArg,
+    inputs: &mut Punctuated<FnArg, syn::token::Comma>,
+    output: &mut Vec<(String, syn::Type)>,
+) -> syn::Result<()> {
+    // No matter what, we always keep the primary:
+    inputs.push(param.clone());
+
+    // There is no reasonable way to differentiate methods containing self.
+    let mut pat_ty = match param {
+        FnArg::Typed(pat_ty) => pat_ty,
+        FnArg::Receiver(r) => return Err(Error::new_spanned(r, "self not supported!")),
+    };
+
+    // Decide if we add a shaddow to inputs or outputs:
+    match act {
+        Activity::Active => {
+            // Used as linear factor
+            inputs.push(FnArg::Typed(pat_ty.clone()));
+
+            // Active implies non-ref type
+            let arg_name = match &*pat_ty.pat {
+                syn::Pat::Ident(pat_ident) => pat_ident.ident.to_string(),
+                _ => "arg".to_owned(), // patterns aren't allowed in declarations anyway
+            };
+            let ty = *pat_ty.ty;
+            if let Type::Ptr(_) | Type::Reference(_) = ty {
+                return Err(Error::new_spanned(
+                    ty,
+                    "Active shall not be used for Pointers or References! Use Gradient or Duplicated.",
+                ));
+            }
+            output.push((arg_name, ty))
+        }
+        // The shadow of a callback is the derivative of the callee, typed by the callbacks module.
+        Activity::Duplicated if matches!(*pat_ty.ty, Type::BareFn(_)) => {
+            inputs.push(FnArg::Typed(pat_ty));
+        }
+        Activity::Gradient | Activity::Duplicated => {
+            // Dup and Gradient require ref type
+            let not_a_ref = |ty: &Type| {
+                Error::new_spanned(
+                    ty,
+                    "Duplicated and Gradient shall only be used for Pointers or References! Use Active instead.",
+                )
+            };
+            if parallel_context {
+                // Atomics are only modified through shared references.
+                let elem = match *pat_ty.ty {
+                    Type::Ptr(ref mut ty_ptr) => {
+                        ty_ptr.const_token = Some(Default::default());
+                        ty_ptr.mutability = None;
+                        &mut ty_ptr.elem
+                    }
+                    Type::Reference(ref mut ty_ref) => {
+                        ty_ref.mutability = None;
+                        &mut ty_ref.elem
+                    }
+                    ref ty => return Err(not_a_ref(ty)),
+                };
+                **elem = atomic_shadow(elem).ok_or_else(|| {
+                    let ty_str = elem.to_token_stream().to_string();
+                    Error::new_spanned(
+                        &elem,
+                        format!("Can't accumulate gradients of `{ty_str}` safely in a parallel context! Only f32/f64 and arrays or slices of them are supported."),
+                    )
+                })?;
+            } else {
+                match *pat_ty.ty {
+                    // We modify the shaddow to make sure it's mutable,
+                    // since we will add the gradients to it.
+                    Type::Ptr(ref mut ty_ptr) => {
+                        ty_ptr.mutability = Some(Default::default());
+                    }
+                    Type::Reference(ref mut ty_ref) => {
+                        ty_ref.mutability = Some(Default::default());
+                    }
+                    ref ty => return Err(not_a_ref(ty)),
+                }
+                tangent_shadow(&mut pat_ty.ty, tangents);
+            }
+            inputs.push(FnArg::Typed(pat_ty));
+        }
+        Activity::Constant => {}
+    }
+    Ok(())
+}
+
+/// One activity per input parameter, no matter how the user specified them.
+///
+/// For `Auto`, the inferred activities are documented on `fnc`.
+#[doc(hidden)]
+pub(crate) fn resolve_activities(
+    info: Granularity,
+    fnc: &mut ForeignItemFn,
+) -> syn::Result<Vec<Activity>> {
+    let activities: Vec<Activity> = match info {
+        Granularity::All(activity) => vec![activity; fnc.sig.inputs.len()],
+        Granularity::PerInput(activities) => {
+            check_activity_count(fnc, activities.len())?;
+            activities
+        }
+        Granularity::Auto(overrides) => infer_activities(fnc, &overrides, |class| match class {
+            TypeClass::Float => Some(Activity::Active),
+            TypeClass::FloatPointer => Some(Activity::Duplicated),
+            TypeClass::Integral => Some(Activity::Constant),
+            TypeClass::Unknown => None,
+        })?,
+    };
+    Ok(activities)
+}
+
+#[doc(hidden)]
+pub(crate) fn adjust_input_parameters(
+    info: Granularity,
+    parallel_context: bool,
+    tangents: &Tangents,
+    fnc: &mut ForeignItemFn,
+) -> syn::Result<Vec<(String, syn::Type)>> {
+    let mut ret_grad_extra_args: Vec<(String, syn::Type)> = vec![];
+    let mut new_params: Punctuated<syn::FnArg, syn::token::Comma> = Punctuated::new();
+
+    let activities = resolve_activities(info, fnc)?;
+    let params = &fnc.sig.inputs;
+    for (&act, param) in activities.iter().zip(params.iter()) {
+        handle_param_rev(
+            act,
+            parallel_context,
+            tangents,
+            param.clone(),
+            &mut new_params,
+            &mut ret_grad_extra_args,
+        )?;
+    }
+    fnc.sig.inputs = new_params;
+    Ok(ret_grad_extra_args)
+}
+
+#[doc(hidden)]
+pub(crate) fn adjust_output_parameters(
+    extra_out_params: Vec<(String, syn::Type)>,
+    infos: RevInfo,
+    fnc: &mut ForeignItemFn,
+) -> syn::Result<Option<syn::ItemStruct>> {
+    let ret_act = infos.return_activity;
+
+    // 1. If we don't add return values, we can return early :)
+    if extra_out_params.is_empty() {
+        match ret_act {
+            ReturnActivity::None | ReturnActivity::Constant => return Ok(None),
+            ReturnActivity::Ignore => {
+                // We also drop the primary return value
+                fnc.sig.output = ReturnType::Default;
+                return Ok(None);
+            }
+            _ => {} // continue
+        };
+    }
+
+    // 2. If we add exactly one type (and previously returned () ),
+    // then we can return the type directly, without struct around it.
+    // Then we also don't have to define a return struct, thus return None.
+    if extra_out_params.len() == 1 && ret_act == ReturnActivity::None {
+        fnc.sig.output =
+            ReturnType::Type(Default::default(), Box::new(extra_out_params[0].1.clone()));
+        return Ok(None);
+    }
+
+    // 3. We modify it and end up with multiple types to return,
+    // so let's start by creating a new return struct to play with.
+    //let mut new_ret_struct = create_ret_struct(infos.grad_fnc_name, fnc.sig.clone());
+    let mode = types::DiffConfig::Rev(infos);
+    let mut new_ret_struct = create_ret_struct(mode.clone(), fnc.sig.clone());
+
+    // 4.a Add the gradient of the primary return, if appropriate
+    if ret_act == ReturnActivity::Active || ret_act == ReturnActivity::Gradient {
+        let prev_ret = match &fnc.sig.output {
+            syn::ReturnType::Default => {
+                return Err(Error::new(
+                    fnc.sig.ident.span(),
+                    "Your function returns (), so please don't specify a return activity!",
+                ));
+            }
+            syn::ReturnType::Type(_, inner) => *inner.clone(),
+        };
+
+        if let syn::Fields::Named(ref mut inner) = new_ret_struct.fields {
+            let grad_name = "primary_grad".to_owned();
+            inner.named.push(make_field(prev_ret, grad_name));
+        } else {
+            unreachable!();
+        }
+    }
+
+    // 4.b If we have active inputs, add them
+    for (arg_num, (arg_name, ret_type)) in extra_out_params.iter().enumerate() {
+        let extra_ret = ret_type;
+        match &mut new_ret_struct.fields {
+            syn::Fields::Named(inner) => inner.named.push(make_field(
+                extra_ret.clone(),
+                mode.field_name(arg_num, arg_name).to_string(),
+            )),
+            _ => unreachable!(),
+        }
+    }
+
+    // 5. Now adjust our function to return the new strucht
+    let path_seg: PathSegment = PathSegment {
+        ident: new_ret_struct.clone().ident,
+        arguments: PathArguments::None,
+    };
+    let mut segments: Punctuated<PathSegment, Token![::]> = Punctuated::new();
+    segments.push(path_seg);
+    let path = syn::Path {
+        leading_colon: None,
+        segments,
+    };
+    let type_path: syn::TypePath = syn::TypePath { qself: None, path };
+    let inner_type: Box<syn::Type> = Box::new(syn::Type::Path(type_path));
+    fnc.sig.output = syn::ReturnType::Type(Default::default(), inner_type);
+
+    Ok(Some(new_ret_struct))
+}
+
+impl fmt::Display for RevInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mode = "reverse-mode";
+        let name = self
+            .grad_fnc_name
+            .as_ref()
+            .map_or("<unnamed>".to_owned(), |n| n.to_string());
+        let par = self.parallel_context;
+        let input = &self.input_activity;
+        let ret = self.return_activity;
+        let output = format!(
+            "handling {name}\nusing {mode}\nwith input activity {input}\nwith output activity {ret:?}\nparallel-context: {par}"
+            );
+        write!(f, "{output}")
+    }
+}
+impl fmt::Display for Granularity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Granularity::All(act) => fmt_granularity(f, "All", &[*act], &[]),
+            Granularity::PerInput(acts) => fmt_granularity(f, "PerInput", acts, &[]),
+            Granularity::Auto(overrides) => fmt_granularity(f, "Auto", &[], overrides),
+        }
+    }
+}
+impl Parse for Activity {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let ident: Ident = input.parse()?;
+        match ident.to_string().as_str() {
+            "Active" => Ok(Activity::Active),
+            "Gradient" => Ok(Activity::Gradient),
+            "Constant" => Ok(Activity::Constant),
+            "Duplicated" => Ok(Activity::Duplicated),
+            _ => Err(Error::new(
+                ident.span(),
+                "Only supporting Active/Duplicated/Gradient/Constant here!",
+            )),
+        }
+    }
+}
+
+impl Parse for ReturnActivity {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let ident: Ident = input.parse()?;
+        let out = match ident.to_string().as_str() {
+            "Active" => ReturnActivity::Active,
+            "Gradient" => ReturnActivity::Gradient,
+            "Constant" => ReturnActivity::Constant,
+            "Ignore" => ReturnActivity::Ignore,
+            "None" => ReturnActivity::None,
+            _ => return Err(Error::new(ident.span(), "Failed parsing return activity. Please specify None if you return () and an activity otherwise!")),
+        };
+        Ok(out)
+    }
+}
+
+impl Parse for Granularity {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let category: Ident = input.parse()?;
+        if category == "Auto" {
+            return Ok(Granularity::Auto(parse_overrides(input)?));
+        }
+
+        let content;
+        let _paren_token = parenthesized!(content in input);
+        let activities: Punctuated<Activity, Token![,]> =
+            content.parse_terminated(Activity::parse)?;
+        let activities: Vec<Activity> = activities.into_iter().collect();
+        match category.to_string().as_str() {
+            "All" if activities.len() == 1 => Ok(Granularity::All(activities[0])),
+            "All" => Err(syn::Error::new(
+                category.span(),
+                "All expects exactly one activity!",
+            )),
+            "PerInput" => Ok(Granularity::PerInput(activities)),
+            _ => Err(syn::Error::new(
+                category.span(),
+                format!("Expected All, PerInput or Auto. Got {category}"),
+            )),
+        }
+    }
+}
@@ -0,0 +1,231 @@
+//! Finding all `differentiate_ext` attributes in the source files of a crate,
+//! on functions, foreign functions and extern blocks, and all `declare_derivative!` entries.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use syn::punctuated::Punctuated;
+use syn::visit::Visit;
+use syn::{ForeignItemFn, Ident, ItemFn, ItemForeignMod, ItemMacro, ItemStruct, Token};
+
+use crate::declare::{derivatives, Declarations};
+use crate::explain::{explain_params, ParamInfo};
+use crate::{
+    extern_block_derivative, foreign_derivative, generate, is_diff_attr, DiffConfig, Generated,
+};
+
+/// One `differentiate_ext` attribute or `declare_derivative!` entry,
+/// together with everything it generates.
+#[derive(Clone)]
+pub struct Found {
+    pub file: PathBuf,
+    /// The line of the attribute, or of the path of the declared function.
+    pub line: usize,
+    pub config: DiffConfig,
+    pub generated: Generated,
+    pub params: Vec<ParamInfo>,
+}
+
+#[derive(Debug)]
+pub enum ScanError {
+    Io(PathBuf, std::io::Error),
+    Parse(PathBuf, syn::Error),
+}
+
+impl fmt::Display for ScanError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ScanError::Io(path, e) => write!(f, "{}: {e}", path.display()),
+            ScanError::Parse(path, e) => {
+                let start = e.span().start();
+                write!(f, "{}:{}: {e}", path.display(), start.line)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ScanError {}
+
+struct FnVisitor<'a> {
+    file: &'a Path,
+    found: Vec<Found>,
+    error: Option<syn::Error>,
+}
+
+impl FnVisitor<'_> {
+    /// Records what one attribute or macro entry generates, or the first error.
+    fn record(&mut self, line: usize, result: syn::Result<(DiffConfig, Generated)>) {
+        let found = result.and_then(|(mut config, generated)| {
+            config.resolve_name(&generated.primal.sig.ident)?;
+            let params = explain_params(&config, &generated.primal.sig)?;
+            Ok(Found {
+                file: self.file.to_owned(),
+                line,
+                config,
+                generated,
+                params,
+            })
+        });
+        match found {
+            Ok(found) => self.found.push(found),
+            Err(e) => self.error = Some(e),
+        }
+    }
+}
+
+impl<'ast> Visit<'ast> for FnVisitor<'_> {
+    fn visit_item_fn(&mut self, item: &'ast ItemFn) {
+        for (i, attr) in item.attrs.iter().enumerate() {
+            if !is_diff_attr(attr) || self.error.is_some() {
+                continue;
+            }
+            // Mimic the expansion of stacked attributes,
+            // where each one only sees the attributes below it.
+            let mut primal = item.clone();
+            primal.attrs = item.attrs[i + 1..].to_vec();
+            let result = attr.parse_args::<DiffConfig>().and_then(|config| {
+                let generated = generate(config.clone(), &primal)?;
+                Ok((config, generated))
+            });
+            self.record(attr.pound_token.span.start().line, result);
+        }
+        syn::visit::visit_item_fn(self, item);
+    }
+
+    fn visit_item_foreign_mod(&mut self, item: &'ast ItemForeignMod) {
+        for (i, attr) in item.attrs.iter().enumerate() {
+            if !is_diff_attr(attr) || self.error.is_some() {
+                continue;
+            }
+            let mut block = item.clone();
+            block.attrs = item.attrs[i + 1..].to_vec();
+            let result = attr.parse_args::<DiffConfig>().and_then(|config| {
+                let generated = extern_block_derivative(config.clone(), &block)?;
+                Ok((config, generated))
+            });
+            self.record(attr.pound_token.span.start().line, result);
+        }
+        syn::visit::visit_item_foreign_mod(self, item);
+    }
+
+    fn visit_foreign_item_fn(&mut self, item: &'ast ForeignItemFn) {
+        for (i, attr) in item.attrs.iter().enumerate() {
+            if !is_diff_attr(attr) || self.error.is_some() {
+                continue;
+            }
+            let mut primal = item.clone();
+            primal.attrs = item.attrs[i + 1..].to_vec();
+            let result = attr.parse_args::<DiffConfig>().and_then(|config| {
+                let generated = foreign_derivative(config.clone(), &primal)?;
+                Ok((config, generated))
+            });
+            self.record(attr.pound_token.span.start().line, result);
+        }
+        syn::visit::visit_foreign_item_fn(self, item);
+    }
+
+    fn visit_item_macro(&mut self, item: &'ast ItemMacro) {
+        let is_declare = item
+            .mac
+            .path
+            .segments
+            .last()
+            .is_some_and(|seg| seg.ident == "declare_derivative");
+        if is_declare && self.error.is_none() {
+            let result = syn::parse2::<Declarations>(item.mac.tokens.clone())
+                .and_then(|declarations| derivatives(&declarations));
+            match result {
+                Ok(derivatives) => {
+                    for (span, config, generated) in derivatives {
+                        self.record(span.start().line, Ok((config, generated)));
+                    }
+                }
+                Err(e) => self.error = Some(e),
+            }
+        }
+        syn::visit::visit_item_macro(self, item);
+    }
+}
+
+struct StructVisitor {
+    structs: Vec<ItemStruct>,
+}
+
+impl<'ast> Visit<'ast> for StructVisitor {
+    fn visit_item_struct(&mut self, item: &'ast ItemStruct) {
+        let repr_c = item.attrs.iter().any(|attr| {
+            attr.path.is_ident("repr")
+                && attr
+                    .parse_args_with(Punctuated::<Ident, Token![,]>::parse_terminated)
+                    .is_ok_and(|reprs| reprs.iter().any(|repr| repr == "C"))
+        });
+        if repr_c {
+            self.structs.push(item.clone());
+        }
+        syn::visit::visit_item_struct(self, item);
+    }
+}
+
+fn parse_file(path: &Path) -> Result<syn::File, ScanError> {
+    let src = std::fs::read_to_string(path).map_err(|e| ScanError::Io(path.to_owned(), e))?;
+    syn::parse_file(&src).map_err(|e| ScanError::Parse(path.to_owned(), e))
+}
+
+/// Finds all derivatives declared in one source file.
+pub fn scan_file(path: &Path) -> Result<Vec<Found>, ScanError> {
+    let file = parse_file(path)?;
+    let mut visitor = FnVisitor {
+        file: path,
+        found: vec![],
+        error: None,
+    };
+    visitor.visit_file(&file);
+    match visitor.error {
+        Some(e) => Err(ScanError::Parse(path.to_owned(), e)),
+        None => Ok(visitor.found),
+    }
+}
+
+/// Finds all derivatives declared in the `.rs` files below `dir`, sorted by path.
+pub fn scan_dir(dir: &Path) -> Result<Vec<Found>, ScanError> {
+    let mut files = vec![];
+    collect_rs_files(dir, &mut files)?;
+    files.sort();
+    let mut found = vec![];
+    for file in files {
+        found.extend(scan_file(&file)?);
+    }
+    Ok(found)
+}
+
+/// Finds all `#[repr(C)]` structs in one source file.
+pub fn repr_c_structs(path: &Path) -> Result<Vec<ItemStruct>, ScanError> {
+    let mut visitor = StructVisitor { structs: vec![] };
+    visitor.visit_file(&parse_file(path)?);
+    Ok(visitor.structs)
+}
+
+/// Finds all `#[repr(C)]` structs in all `.rs` files below `dir`, sorted by path.
+pub fn repr_c_structs_in_dir(dir: &Path) -> Result<Vec<ItemStruct>, ScanError> {
+    let mut files = vec![];
+    collect_rs_files(dir, &mut files)?;
+    files.sort();
+    let mut structs = vec![];
+    for file in files {
+        structs.extend(repr_c_structs(&file)?);
+    }
+    Ok(structs)
+}
+
+fn collect_rs_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), ScanError> {
+    let entries = std::fs::read_dir(dir).map_err(|e| ScanError::Io(dir.to_owned(), e))?;
+    for entry in entries {
+        let path = entry.map_err(|e| ScanError::Io(dir.to_owned(), e))?.path();
+        if path.is_dir() {
+            collect_rs_files(&path, files)?;
+        } else if path.extension().is_some_and(|ext| ext == "rs") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
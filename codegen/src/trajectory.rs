@@ -0,0 +1,154 @@
+//! Adjoints of whole trajectories of a step function, `#[trajectory(d_step)]`.
+//!
+//! Time stepping code applies a function like `fn step(state: &mut State, dt: f64)`
+//! thousands of times. Storing every intermediate state for the reverse pass doesn't scale,
+//! so we generate a driver which stores only a few snapshots and recomputes the states between
+//! them, see `autodiff_runtime::revolve`.
+
+use quote::quote;
+use syn::{
+    parse_quote, Error, FnArg, ForeignItemFn, Ident, ItemFn, Pat, Result, ReturnType, Type,
+    Visibility,
+};
+
+use crate::modes::reverse::{self, Activity, ReturnActivity};
+use crate::{attrs_below, generate, sibling_configs, DiffConfig};
+
+/// Name of the driver, `<primal>_trajectory_adjoint`.
+pub fn driver_name(primal: &Ident) -> Ident {
+    Ident::new(&format!("{primal}_trajectory_adjoint"), primal.span())
+}
+
+fn param_name(param: &FnArg) -> Result<Ident> {
+    match param {
+        FnArg::Typed(pat_ty) => match &*pat_ty.pat {
+            Pat::Ident(pat_ident) => Ok(pat_ident.ident.clone()),
+            pat => Err(Error::new_spanned(
+                pat,
+                "Please use a plain parameter name!",
+            )),
+        },
+        FnArg::Receiver(r) => Err(Error::new_spanned(r, "self not supported!")),
+    }
+}
+
+/// Generates the driver for the step function `primal`, based on its derivative `derivative`.
+///
+/// The derivative has to be declared by a `differentiate_ext` attribute below ours,
+/// in reverse mode, with the state as first and `Duplicated` parameter and all others `Constant`.
+pub fn trajectory(derivative: &Ident, primal: &ItemFn) -> Result<ItemFn> {
+    let sig = &primal.sig;
+    let config = sibling_configs(primal)?
+        .into_iter()
+        .find(|config| config.name() == *derivative)
+        .ok_or_else(|| {
+            Error::new(
+                derivative.span(),
+                format!("No derivative `{derivative}` found, please place #[trajectory] above the differentiate_ext attribute declaring it!"),
+            )
+        })?;
+    let rev = match &config {
+        DiffConfig::Rev(rev) => rev.clone(),
+        DiffConfig::Fwd(_) => {
+            return Err(Error::new(
+                derivative.span(),
+                "The adjoint of a trajectory requires a reverse mode derivative!",
+            ))
+        }
+    };
+    let state_ty = match sig.inputs.first() {
+        Some(FnArg::Typed(pat_ty)) => match &*pat_ty.ty {
+            Type::Reference(r) if r.mutability.is_some() => (*r.elem).clone(),
+            ty => {
+                return Err(Error::new_spanned(
+                    ty,
+                    "The state of a step function has to be its first parameter, passed as `&mut State`!",
+                ))
+            }
+        },
+        _ => {
+            return Err(Error::new(
+                sig.ident.span(),
+                "The state of a step function has to be its first parameter, passed as `&mut State`!",
+            ))
+        }
+    };
+    if let ReturnType::Type(_, ty) = &sig.output {
+        return Err(Error::new_spanned(
+            ty,
+            "A step function only updates its state and can't return a value!",
+        ));
+    }
+
+    // Resolving might annotate the declaration, so we work on a scratch copy.
+    let mut scratch = ForeignItemFn {
+        attrs: vec![],
+        vis: Visibility::Inherited,
+        sig: sig.clone(),
+        semi_token: Default::default(),
+    };
+    let activities = reverse::resolve_activities(rev.input_activity, &mut scratch)?;
+    let state_only = activities.first() == Some(&Activity::Duplicated)
+        && activities[1..]
+            .iter()
+            .all(|activity| *activity == Activity::Constant);
+    if !state_only || rev.return_activity != ReturnActivity::None {
+        return Err(Error::new(
+            derivative.span(),
+            format!("`{derivative}` needs the state as only Duplicated parameter, all other parameters Constant, and None as return activity!"),
+        ));
+    }
+
+    // Generate the derivative like its attribute will, which only sees the attributes below it.
+    let below = attrs_below(primal, derivative);
+    // What we call is the derivative as users see it, so the wrapper if there is one.
+    let generated = generate(config, &below)?;
+    let callee = match &generated.wrapper {
+        Some(wrapper) => wrapper.sig.clone(),
+        None => generated.declaration.sig.clone(),
+    };
+    // Only the state gets a shadow, the other parameters are passed through unchanged.
+    let shadow_ty = match &callee.inputs[1] {
+        FnArg::Typed(pat_ty) => &pat_ty.ty,
+        FnArg::Receiver(_) => unreachable!("receivers are rejected earlier"),
+    };
+
+    let name = &sig.ident;
+    let driver = driver_name(name);
+    let vis = &primal.vis;
+    let rest: Vec<&FnArg> = sig.inputs.iter().skip(1).collect();
+    let args = rest
+        .iter()
+        .map(|param| param_name(param))
+        .collect::<Result<Vec<_>>>()?;
+    let step = match sig.unsafety {
+        Some(_) => quote! { unsafe { #name(state, #(#args),*) } },
+        None => quote! { #name(state, #(#args),*) },
+    };
+    let doc = format!(
+        " The adjoint of `n_steps` applications of [`{name}`] to `state0`, computed with `{derivative}`.\n\n \
+        `d_state` holds the adjoint of the final state and is overwritten with the adjoint of `state0`.\n \
+        At most `snapshots` intermediate states are stored, the others are recomputed,\n \
+        see `autodiff_runtime::revolve`.\n\n \
+        # Safety\n\n \
+        This calls `{derivative}`, so its requirements apply."
+    );
+    Ok(parse_quote! {
+        #[doc = #doc]
+        #vis unsafe fn #driver(
+            state0: &#state_ty,
+            d_state: #shadow_ty,
+            n_steps: usize,
+            snapshots: usize,
+            #(#rest),*
+        ) {
+            ::autodiff_runtime::revolve(
+                state0,
+                n_steps,
+                snapshots,
+                |state| #step,
+                |state| unsafe { #derivative(state, d_state, #(#args),*) },
+            )
+        }
+    })
+}
@@ -0,0 +1,114 @@
+//! Type trees of the primal parameters, see `autodiff_runtime::type_tree`.
+//!
+//! The layout of a type is only known to rustc, so we don't compute the trees here.
+//! Instead we emit a constant which builds them from `autodiff_runtime::HasTypeTree`
+//! while compiling, and a static holding the resulting text for the backend.
+//! `#[derive(HasTypeTree)]` implements the trait for `#[repr(C)]` structs through `offset_of!`.
+
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{
+    parse_quote, Data, DeriveInput, Error, FnArg, Ident, Index, Item, Member, Result, ReturnType,
+    Signature, Type,
+};
+
+use crate::buffers;
+use crate::differentiable::is_repr_c;
+use crate::export_attr;
+
+/// Name of the static holding the type trees for the derivative `name`.
+pub fn static_name(name: &Ident) -> Ident {
+    Ident::new(&format!("__enzyme_typetree_{name}"), name.span())
+}
+
+/// Implements `autodiff_runtime::HasTypeTree` for a `#[derive(HasTypeTree)]`.
+pub fn derive(input: &DeriveInput) -> Result<TokenStream> {
+    let name = &input.ident;
+    let fields = match &input.data {
+        Data::Struct(data) => &data.fields,
+        _ => {
+            return Err(Error::new(
+                name.span(),
+                "HasTypeTree can only be derived for structs!",
+            ))
+        }
+    };
+    if !is_repr_c(input) {
+        return Err(Error::new(
+            name.span(),
+            "HasTypeTree requires #[repr(C)], otherwise the layout of the struct isn't fixed!",
+        ));
+    }
+    if !input.generics.params.is_empty() {
+        return Err(Error::new_spanned(
+            &input.generics,
+            "HasTypeTree can't be derived for generic structs yet!",
+        ));
+    }
+    let rt = quote! { ::autodiff_runtime };
+    let members = fields
+        .iter()
+        .enumerate()
+        .map(|(i, field)| match &field.ident {
+            Some(ident) => Member::Named(ident.clone()),
+            None => Member::Unnamed(Index::from(i)),
+        });
+    let tys = fields.iter().map(|field| &field.ty);
+    Ok(quote! {
+        unsafe impl #rt::HasTypeTree for #name {
+            const TREE: #rt::type_tree::TypeTree =
+                #rt::type_tree::TypeTree::empty(::core::mem::size_of::<Self>())
+                #(.field(
+                    ::core::mem::offset_of!(Self, #members),
+                    &<#tys as #rt::HasTypeTree>::TREE,
+                ))*;
+        }
+    })
+}
+
+/// The constant building the type trees of the primal shim for the derivative `name`,
+/// and the static exporting them.
+pub(crate) fn items(
+    name: &Ident,
+    primal: &Signature,
+    buffer: &[syn::Path],
+    prefix: &str,
+) -> Result<Vec<Item>> {
+    // The shim passes containers as pointer and length, arrays keep their layout in its struct.
+    let mut shim = primal.clone();
+    buffers::lower_shim(&mut shim, &buffers::buffer_params(primal, buffer));
+    let rt = quote! { ::autodiff_runtime };
+    let params = shim
+        .inputs
+        .iter()
+        .map(|param| match param {
+            FnArg::Typed(pat_ty) => Ok(&*pat_ty.ty),
+            FnArg::Receiver(r) => Err(Error::new_spanned(r, "self not supported!")),
+        })
+        .collect::<Result<Vec<&Type>>>()?;
+    let ret = match &shim.output {
+        ReturnType::Type(_, ty) if !matches!(&**ty, Type::Tuple(t) if t.elems.is_empty()) => {
+            Some(quote! { .ret(&<#ty as #rt::HasTypeTree>::TREE) })
+        }
+        _ => None,
+    };
+    let ident = static_name(name);
+    let text = Ident::new(&format!("__enzyme_typetree_text_{name}"), name.span());
+    let export = export_attr(&ident, prefix);
+    Ok(vec![
+        parse_quote! {
+            #[doc(hidden)]
+            #[allow(non_upper_case_globals)]
+            const #text: #rt::type_tree::TypeTreeText = #rt::type_tree::TypeTreeText::new()
+                #(.param(&<#params as #rt::HasTypeTree>::TREE))*
+                #ret;
+        },
+        parse_quote! {
+            #[doc(hidden)]
+            #[allow(non_upper_case_globals)]
+            #[used]
+            #export
+            static #ident: [u8; #text.len()] = #text.bytes();
+        },
+    ])
+}
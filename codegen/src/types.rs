@@ -0,0 +1,579 @@
+//! These are our most generic types
+//!
+//! Based on the concrete Mode our macro might only accept a specific subset of these types.
+//! Details are then specified in the documentation of the corresponding modes.
+
+use core::fmt;
+use std::num::NonZeroU32;
+
+use crate::metadata::Metadata;
+use crate::modes::forward::{FwdInfo, RuntimeWidth};
+use crate::modes::reverse::{ReturnActivity, RevInfo};
+use crate::type_tree;
+
+use super::modes::*;
+use quote::ToTokens;
+use syn::parse::{Parse, ParseStream};
+use syn::*;
+use syn::{Ident, Token};
+
+/// The central Enum being created from macro input.
+///
+/// It's usually parsed from the arguments of a `differentiate_ext` attribute.
+/// Please see the documentation of the specific modes to learn how to adjust it's parameters.
+#[derive(Clone)]
+pub enum DiffConfig {
+    Fwd(FwdInfo),
+    Rev(RevInfo),
+}
+impl DiffConfig {
+    pub fn name(&self) -> syn::Ident {
+        let name = match self {
+            DiffConfig::Fwd(f) => &f.grad_fnc_name,
+            DiffConfig::Rev(r) => &r.grad_fnc_name,
+        };
+        name.clone()
+            .expect("derivative names are resolved before usage, see resolve_name")
+    }
+    /// If the user didn't name the derivative, we derive the name from the naming template.
+    pub fn resolve_name(&mut self, primal: &Ident) -> Result<()> {
+        let (mode, width) = match self {
+            DiffConfig::Fwd(FwdInfo {
+                runtime_width: Some(width),
+                ..
+            }) => ("fwd", width.to_string().replace("::", "_")),
+            DiffConfig::Fwd(f) if u32::from(f.width) > 1 => ("fwd", f.width.to_string()),
+            DiffConfig::Fwd(_) => ("fwd", String::new()),
+            DiffConfig::Rev(_) => ("rev", String::new()),
+        };
+        let template = self.options().name_template();
+        let grad_fnc_name = match self {
+            DiffConfig::Fwd(f) => &mut f.grad_fnc_name,
+            DiffConfig::Rev(r) => &mut r.grad_fnc_name,
+        };
+        if grad_fnc_name.is_none() {
+            let name = render(
+                &template,
+                &[
+                    ("fn", &primal.to_string()),
+                    ("mode", mode),
+                    ("width", &width),
+                ],
+            );
+            *grad_fnc_name = Some(template_ident(&name, primal.span())?);
+        }
+        Ok(())
+    }
+    /// The name of the struct returned by our derivative, if we need one.
+    pub fn ret_name(&self) -> Ident {
+        let name = self.name();
+        let ret = render(
+            &self.options().ret_template(),
+            &[("name", &name.to_string())],
+        );
+        // The ret template was already validated while parsing.
+        Ident::new(&ret, name.span())
+    }
+    /// The name of the return struct field holding the gradient of the i-th active input.
+    pub(crate) fn field_name(&self, i: usize, arg: &str) -> Ident {
+        let field = render(
+            &self.options().field_template(),
+            &[
+                ("name", &self.name().to_string()),
+                ("i", &i.to_string()),
+                ("arg", arg),
+            ],
+        );
+        Ident::new(&field, proc_macro2::Span::mixed_site())
+    }
+    pub fn options(&self) -> &Options {
+        match self {
+            DiffConfig::Fwd(f) => &f.options,
+            DiffConfig::Rev(r) => &r.options,
+        }
+    }
+    /// The name under which the linker will see our derivative.
+    pub fn symbol(&self) -> String {
+        self.options().symbol_prefix() + &self.name().to_string()
+    }
+    /// Collects the settings which the backend can't read from the declaration itself.
+    pub(crate) fn metadata(&self, primal: &str) -> Metadata {
+        let mut meta = Metadata::default();
+        meta.push("name", self.name());
+        meta.push("symbol", self.symbol());
+        meta.push("primal", primal);
+        match self {
+            DiffConfig::Fwd(f) => {
+                meta.push("mode", "forward");
+                match f.runtime_width {
+                    // The width is passed as last argument.
+                    Some(_) => meta.push("width", "dyn"),
+                    None => meta.push("width", f.width),
+                }
+            }
+            DiffConfig::Rev(r) => {
+                meta.push("mode", "reverse");
+                meta.push("parallel_context", r.parallel_context);
+                if !r.options.checkpoint.is_empty() {
+                    let names: Vec<String> = r
+                        .options
+                        .checkpoint
+                        .iter()
+                        .map(|path| path.to_token_stream().to_string().replace(' ', ""))
+                        .collect();
+                    meta.push("checkpoint", names.join(","));
+                }
+            }
+        }
+        if let Some(of) = &self.options().of {
+            meta.push("of", of);
+        }
+        if !self.options().callback.is_empty() {
+            let callbacks: Vec<String> = self
+                .options()
+                .callback
+                .iter()
+                .map(|(param, path)| {
+                    format!(
+                        "{param}={}",
+                        path.to_token_stream().to_string().replace(' ', "")
+                    )
+                })
+                .collect();
+            meta.push("callback", callbacks.join(","));
+        }
+        if self.options().type_tree {
+            let symbol =
+                self.options().symbol_prefix() + &type_tree::static_name(&self.name()).to_string();
+            meta.push("type_tree", symbol);
+        }
+        for (key, value) in &self.options().backend {
+            meta.push(key, value);
+        }
+        meta
+    }
+    pub(crate) fn ret(&self) -> ReturnActivity {
+        match self {
+            DiffConfig::Fwd(f) => f.return_activity.into(),
+            DiffConfig::Rev(r) => r.return_activity,
+        }
+    }
+}
+impl fmt::Display for DiffConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DiffConfig::Fwd(fwd) => fwd.fmt(f),
+            DiffConfig::Rev(rev) => rev.fmt(f),
+        }
+    }
+}
+impl Parse for DiffConfig {
+    fn parse(input: ParseStream) -> Result<Self> {
+        // The name is optional, otherwise it's derived from the primal name later.
+        let grad_fnc_name: Option<Ident> = if input.peek(kw::Forward) || input.peek(kw::Reverse) {
+            None
+        } else {
+            let name = input.parse()?;
+            let _: Token![,] = input.parse()?;
+            Some(name)
+        };
+        let mode: Mode = input.parse()?;
+        let _: Token![,] = input.parse()?;
+
+        match mode {
+            Mode::Forward(width) => FwdMode::parse(grad_fnc_name, input, width, None),
+            Mode::ForwardRuntime(width) => {
+                FwdMode::parse(grad_fnc_name, input, Width::MIN, Some(width))
+            }
+            Mode::Reverse => RevMode::parse(grad_fnc_name, input),
+        }
+    }
+}
+
+/// Optional settings, which can be appended as `key = value` pairs to any mode.
+///
+/// - `prefix = "my_prefix_"` is prepended to the names of all symbols we export or link against,
+///   so derivatives from different modules or crates can't collide at link time.
+///   `prefix = crate` uses the name of the current crate.
+///   Without this option we use the `AUTODIFF_SYMBOL_PREFIX` environment variable if it is set,
+///   e.g. through `cargo:rustc-env` in the build.rs file of your crate.
+/// - `name = "{fn}_grad_{mode}{width}"` is the template for the derivative name,
+///   if none is given as first argument. Defaults to `AUTODIFF_NAME_TEMPLATE` or `d_{fn}`.
+///   `{fn}` is the primal name, `{mode}` is `fwd` or `rev`
+///   and `{width}` is the vector width for vector forward mode, otherwise empty.
+/// - `ret = "{name}_ret"` is the template for the name of the generated return struct.
+///   Defaults to `AUTODIFF_RET_TEMPLATE` or `{name}_ret`, where `{name}` is the derivative name.
+/// - `field = "x{i}"` is the template for the return struct fields holding the gradients of active
+///   inputs. Defaults to `AUTODIFF_FIELD_TEMPLATE` or `x{i}`, where `{i}` counts the active inputs
+///   and `{arg}` is the name of the input. `{name}` is available as well.
+///
+///   The `AUTODIFF_*` environment variables are read while expanding the macros and cargo doesn't
+///   track them, so changing them doesn't rebuild crates which are already compiled.
+///   Set them through `cargo:rustc-env` in a build.rs file together with
+///   `cargo:rerun-if-env-changed`, or run `cargo clean` after changing them.
+/// - `explain` (or `explain = true`) reports the documentation generated for the derivative and its
+///   return struct as a warning at the derivative name, e.g. to check which shadow belongs to which
+///   input. Remove it again before building with `-D warnings`.
+/// - `vis = "pub(crate)"` sets the visibility of the return struct and its fields.
+///   By default they are as visible as the primal function, `vis = "private"` keeps them private.
+/// - `derive = [Copy, Default, PartialEq]` adds derives to the return struct,
+///   which always derives `Clone` and `Debug`.
+/// - `algebra` (or `algebra = true`) implements `Add`, `AddAssign` and `Mul<S>` element-wise
+///   for the return struct, e.g. to accumulate the gradients of mini-batches.
+///   Array fields are added and scaled element by element.
+/// - `layout = array` passes the tangents of vector forward mode as one `[T; N]` array per input,
+///   and returns them in `primary_grad: [T; N]`, instead of `d_x_0 .. d_x_<N-1>`
+///   and `primary_grad0 .. primary_grad<N-1>`. See `Layout`.
+/// - `tangent = [Model]` types the shadows of references and pointers to `Model` as
+///   `<Model as autodiff_runtime::Differentiable>::Tangent`, e.g. `&mut ModelTangent`
+///   for a struct with `#[derive(Differentiable)]`. The paths have to be spelled like in the
+///   signature of the primal. Other types keep shadows of their own type, since a macro can't
+///   see whether a type derives `Differentiable`.
+/// - `buffer = [Matrix]` passes references to containers implementing `autodiff_runtime::AdBuffer`
+///   as a pointer to their elements and a length, like `Vec<T>` and `Box<[T]>`, which are
+///   recognized without being listed, as are slices. The derivative is then a wrapper which takes
+///   the containers. The primal has to take containers by shared reference.
+///   Stacked attributes share the primal shim, so they have to list the same types.
+/// - `checkpoint = [helper_a, helper_b]` asks the backend to recompute calls of these functions
+///   in the reverse pass, instead of caching their intermediate values. This trades memory for
+///   time. Functions marked with `#[checkpoint]` are checkpointed by all derivatives.
+///   Reverse mode only.
+/// - `options(strong_zero, free_memory = false)` passes settings to the backend for this derivative,
+///   see `BACKEND_OPTIONS` for the known keys. Each is a flag, written as `key` or `key = bool`.
+/// - `callback(rhs = d_rhs)` allows `Duplicated` for the function pointer parameter `rhs`.
+///   Its shadow is the derivative `d_rhs` of the callee, declared by another `differentiate_ext`
+///   without a wrapper, and we check that the primal of `d_rhs` has the type of `rhs`.
+/// - `of = d_f` differentiates the derivative `d_f` instead of the primal, e.g. for second
+///   derivatives. `d_f` has to be declared by a `differentiate_ext` attribute below this one,
+///   its signature as users see it is then used as primal. This nests to any order.
+/// - `type_tree` (or `type_tree = true`) emits the memory layout of each parameter of the primal
+///   shim for the backend, so it doesn't have to guess it. All parameter types have to implement
+///   `autodiff_runtime::HasTypeTree`, e.g. through `#[derive(HasTypeTree)]`, and stay within the
+///   limits of `autodiff_runtime::type_tree`.
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    pub prefix: Option<String>,
+    pub name: Option<String>,
+    pub ret: Option<String>,
+    pub field: Option<String>,
+    /// Print the generated documentation as a note while compiling.
+    pub explain: bool,
+    /// Visibility of the return struct and its fields, defaults to the one of the primal.
+    pub vis: Option<Visibility>,
+    /// Derives for the return struct, in addition to `Clone` and `Debug`.
+    pub derive: Vec<Path>,
+    /// Implement `Add`, `AddAssign` and `Mul<scalar>` element-wise for the return struct.
+    pub algebra: bool,
+    /// How vector forward mode passes tangents.
+    pub layout: Layout,
+    /// Types whose shadows are typed as their `Differentiable::Tangent`.
+    pub tangent: Vec<Path>,
+    /// Container types which are passed as pointer and length, besides `Vec` and `Box<[T]>`.
+    pub buffer: Vec<Path>,
+    /// Functions whose calls are recomputed in the reverse pass, written into the metadata.
+    pub checkpoint: Vec<Path>,
+    /// Settings for the backend from the `options(...)` clause, in the given order.
+    pub backend: Vec<(String, bool)>,
+    /// Emit the type trees of the primal parameters next to the declaration.
+    pub type_tree: bool,
+    /// Function pointer parameters and the derivatives of their callees, from `callback(...)`.
+    pub callback: Vec<(Ident, Path)>,
+    /// The derivative which is differentiated instead of the primal, for higher order derivatives.
+    pub of: Option<Ident>,
+}
+
+/// The settings which the backend accepts per derivative in the `options(...)` clause.
+pub const BACKEND_OPTIONS: [(&str, &str); 5] = [
+    (
+        "strong_zero",
+        "multiply by zero seeds strictly, so `0 * inf` and `0 * nan` give 0 in the derivative",
+    ),
+    (
+        "runtime_activity",
+        "check at runtime whether a pointer and its shadow alias, for values whose activity can't be decided while compiling",
+    ),
+    (
+        "free_memory",
+        "free the memory which the primal allocates and the reverse pass caches, defaults to true",
+    ),
+    (
+        "loose_types",
+        "guess the type of memory which type analysis can't decide, instead of failing",
+    ),
+    (
+        "inline_primal",
+        "inline the primal into the derivative before differentiating it",
+    ),
+];
+
+/// How the tangents of vector forward mode are passed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Layout {
+    /// One parameter `d_x_i` per direction and one field `primary_grad<i>` per direction.
+    #[default]
+    Fields,
+    /// One array `d_x: [T; N]` per input and a `primary_grad: [T; N]` field.
+    /// The derivative is then a wrapper around a declaration with the `Fields` layout,
+    /// so the C ABI is the same.
+    Array,
+}
+
+impl Options {
+    /// Parses the remaining `, key = value` pairs after the mode specific arguments.
+    pub(crate) fn parse_trailing(input: ParseStream) -> Result<Self> {
+        let mut options = Options::default();
+        while !input.is_empty() {
+            let _: Token![,] = input.parse()?;
+            if input.is_empty() {
+                break;
+            }
+            let key: Ident = input.parse()?;
+            match key.to_string().as_str() {
+                "prefix" => {
+                    let _: Token![=] = input.parse()?;
+                    let prefix = if input.peek(Token![crate]) {
+                        input.parse::<Token![crate]>()?;
+                        crate_prefix()
+                    } else {
+                        input.parse::<LitStr>()?.value()
+                    };
+                    options.prefix = Some(prefix);
+                }
+                "name" | "ret" | "field" => {
+                    let _: Token![=] = input.parse()?;
+                    let lit: LitStr = input.parse()?;
+                    let template = lit.value();
+                    // Check that the template can produce an identifier, using dummy values.
+                    let example = render(
+                        &template,
+                        &[
+                            ("fn", "f"),
+                            ("mode", "rev"),
+                            ("width", "2"),
+                            ("name", "d_f"),
+                        ],
+                    );
+                    let example = render(&example, &[("i", "0"), ("arg", "x")]);
+                    template_ident(&example, lit.span())?;
+                    match key.to_string().as_str() {
+                        "name" => options.name = Some(template),
+                        "ret" => options.ret = Some(template),
+                        _ => options.field = Some(template),
+                    }
+                }
+                "explain" => options.explain = parse_flag(input)?,
+                "algebra" => options.algebra = parse_flag(input)?,
+                "type_tree" => options.type_tree = parse_flag(input)?,
+                "vis" => {
+                    let _: Token![=] = input.parse()?;
+                    let lit: LitStr = input.parse()?;
+                    let vis = match lit.value().trim() {
+                        "" | "private" => Visibility::Inherited,
+                        vis => syn::parse_str(vis).map_err(|_| {
+                            Error::new(lit.span(), format!("`{vis}` is not a visibility!"))
+                        })?,
+                    };
+                    options.vis = Some(vis);
+                }
+                "layout" => {
+                    let _: Token![=] = input.parse()?;
+                    let layout: Ident = input.parse()?;
+                    options.layout = match layout.to_string().as_str() {
+                        "fields" => Layout::Fields,
+                        "array" => Layout::Array,
+                        _ => {
+                            return Err(Error::new(
+                                layout.span(),
+                                "Expected `fields` or `array` as layout!",
+                            ))
+                        }
+                    };
+                }
+                "options" => {
+                    let content;
+                    parenthesized!(content in input);
+                    while !content.is_empty() {
+                        let knob: Ident = content.parse()?;
+                        let name = knob.to_string();
+                        if !BACKEND_OPTIONS.iter().any(|(known, _)| *known == name) {
+                            let known: Vec<&str> =
+                                BACKEND_OPTIONS.iter().map(|(known, _)| *known).collect();
+                            return Err(Error::new(
+                                knob.span(),
+                                format!(
+                                    "Unknown backend option `{name}`! Known are: {}.",
+                                    known.join(", ")
+                                ),
+                            ));
+                        }
+                        if options.backend.iter().any(|(key, _)| *key == name) {
+                            return Err(Error::new(
+                                knob.span(),
+                                format!("The backend option `{name}` is set twice!"),
+                            ));
+                        }
+                        options.backend.push((name, parse_flag(&content)?));
+                        if content.is_empty() {
+                            break;
+                        }
+                        let _: Token![,] = content.parse()?;
+                    }
+                }
+                "of" => {
+                    let _: Token![=] = input.parse()?;
+                    options.of = Some(input.parse()?);
+                }
+                "callback" => {
+                    let content;
+                    parenthesized!(content in input);
+                    while !content.is_empty() {
+                        let param: Ident = content.parse()?;
+                        if options.callback.iter().any(|(known, _)| *known == param) {
+                            return Err(Error::new(
+                                param.span(),
+                                format!("The callback `{param}` is listed twice!"),
+                            ));
+                        }
+                        let _: Token![=] = content.parse()?;
+                        options.callback.push((param, content.parse()?));
+                        if content.is_empty() {
+                            break;
+                        }
+                        let _: Token![,] = content.parse()?;
+                    }
+                }
+                "derive" => {
+                    let _: Token![=] = input.parse()?;
+                    let content;
+                    bracketed!(content in input);
+                    let derives =
+                        content.parse_terminated::<Path, Token![,]>(Path::parse_mod_style)?;
+                    options.derive.extend(derives);
+                }
+                "tangent" | "buffer" | "checkpoint" => {
+                    let _: Token![=] = input.parse()?;
+                    let content;
+                    bracketed!(content in input);
+                    let paths = content.parse_terminated::<Path, Token![,]>(Path::parse)?;
+                    match key.to_string().as_str() {
+                        "tangent" => options.tangent.extend(paths),
+                        "buffer" => options.buffer.extend(paths),
+                        _ => options.checkpoint.extend(paths),
+                    }
+                }
+                _ => return Err(Error::new(key.span(), format!("Unknown option `{key}`!"))),
+            }
+        }
+        Ok(options)
+    }
+
+    pub fn name_template(&self) -> String {
+        template_or(&self.name, "AUTODIFF_NAME_TEMPLATE", "d_{fn}")
+    }
+
+    pub fn ret_template(&self) -> String {
+        template_or(&self.ret, "AUTODIFF_RET_TEMPLATE", "{name}_ret")
+    }
+
+    pub fn field_template(&self) -> String {
+        template_or(&self.field, "AUTODIFF_FIELD_TEMPLATE", "x{i}")
+    }
+
+    pub fn symbol_prefix(&self) -> String {
+        match &self.prefix {
+            Some(prefix) => prefix.clone(),
+            None => std::env::var("AUTODIFF_SYMBOL_PREFIX").unwrap_or_default(),
+        }
+    }
+}
+
+/// A boolean option, either given as `key = true` / `key = false` or just as `key`.
+fn parse_flag(input: ParseStream) -> Result<bool> {
+    if !input.peek(Token![=]) {
+        return Ok(true);
+    }
+    let _: Token![=] = input.parse()?;
+    Ok(input.parse::<LitBool>()?.value)
+}
+
+fn template_or(template: &Option<String>, env_var: &str, default: &str) -> String {
+    match template {
+        Some(template) => template.clone(),
+        None => std::env::var(env_var).unwrap_or_else(|_| default.to_owned()),
+    }
+}
+
+/// Replaces all `{key}` placeholders in a naming template.
+fn render(template: &str, values: &[(&str, &str)]) -> String {
+    let mut out = template.to_owned();
+    for (key, value) in values {
+        out = out.replace(&format!("{{{key}}}"), value);
+    }
+    out
+}
+
+fn template_ident(name: &str, span: proc_macro2::Span) -> Result<Ident> {
+    match syn::parse_str::<Ident>(name) {
+        Ok(_) => Ok(Ident::new(name, span)),
+        Err(_) => Err(Error::new(
+            span,
+            format!("The naming template produces `{name}`, which is not a valid identifier!"),
+        )),
+    }
+}
+
+fn crate_prefix() -> String {
+    let name = std::env::var("CARGO_CRATE_NAME")
+        .or_else(|_| std::env::var("CARGO_PKG_NAME"))
+        .unwrap_or_default();
+    name.replace('-', "_") + "_"
+}
+
+pub type Width = NonZeroU32;
+
+#[non_exhaustive]
+#[derive(Debug, Clone)]
+pub enum Mode {
+    /// Forward mode is usually recommendable when having few inputs and various outputs.
+    Forward(Width),
+    /// Vector forward mode with a width which is only known after expanding our macro,
+    /// `Forward(WIDTH)` with a `const WIDTH: usize` or `Forward(dyn)`.
+    ForwardRuntime(RuntimeWidth),
+    /// Reverse mode is usually recommendable when having various inputs and few outputs.
+    Reverse, // None if the fnc returns ()
+}
+mod kw {
+    syn::custom_keyword!(Forward);
+    syn::custom_keyword!(Reverse);
+}
+
+impl Parse for Mode {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let lookahead = input.lookahead1();
+        if lookahead.peek(kw::Reverse) {
+            input.parse::<kw::Reverse>()?;
+            Ok(Mode::Reverse)
+        } else if lookahead.peek(kw::Forward) {
+            input.parse::<kw::Forward>()?;
+            if input.peek(Token![,]) {
+                Ok(Mode::Forward(NonZeroU32::new(1).unwrap()))
+            } else {
+                let content;
+                let _paren_token = parenthesized!(content in input);
+                if content.peek(Token![dyn]) {
+                    content.parse::<Token![dyn]>()?;
+                    return Ok(Mode::ForwardRuntime(RuntimeWidth::Dyn));
+                }
+                if !content.peek(LitInt) {
+                    let width: Path = content.parse()?;
+                    return Ok(Mode::ForwardRuntime(RuntimeWidth::Const(width)));
+                }
+                let lit: LitInt = content.parse()?;
+                let val = lit.base10_parse::<NonZeroU32>()?;
+                Ok(Mode::Forward(val))
+            }
+        } else {
+            Err(lookahead.error())
+        }
+    }
+}
@@ -0,0 +1,36 @@
+use autodiff_codegen::annotations::checkpoint;
+
+fn annotate(item: &str) -> syn::Result<String> {
+    let item: syn::ItemFn = syn::parse_str(item)?;
+    checkpoint(&item).map(|out| out.to_string())
+}
+
+#[test]
+fn exported() {
+    let out = annotate("pub fn step(x: f64) -> f64 { x }").unwrap();
+    assert!(out.starts_with("# [inline (never)] # [no_mangle] pub fn step"));
+    assert!(out.contains("static __enzyme_checkpoint_step"));
+    assert!(out.contains(r#"b"name=step\nsymbol=step\ncheckpoint=true\n\0""#));
+}
+
+#[test]
+fn errors() {
+    assert!(annotate("fn step<T>(x: T) -> T { x }").is_err());
+    assert!(annotate("#[inline] fn step(x: f64) -> f64 { x }").is_err());
+    assert!(annotate("#[no_mangle] fn step(x: f64) -> f64 { x }").is_err());
+}
+
+#[test]
+fn inactive() {
+    use autodiff_codegen::annotations::inactive;
+    let mark = |item: &str| inactive(&syn::parse_str(item).unwrap()).map(|out| out.to_string());
+    let out = mark("static mut COUNTER: usize = 0;").unwrap();
+    assert!(out.starts_with("# [no_mangle] static mut COUNTER"));
+    assert!(out.contains(r#"b"name=COUNTER\nsymbol=COUNTER\ninactive=true\n\0""#));
+    let out = mark("fn log(x: f64) {}").unwrap();
+    assert!(out.starts_with("# [inline (never)] # [no_mangle] fn log"));
+    assert!(out.contains("static __enzyme_inactive_log"));
+
+    assert!(mark("const N: usize = 3;").is_err());
+    assert!(mark("#[export_name = \"c\"] static C: usize = 0;").is_err());
+}
@@ -0,0 +1,61 @@
+use autodiff_codegen::declare::{declare, Declarations};
+use autodiff_codegen::{generate, DiffConfig};
+
+fn expand(input: &str) -> syn::Result<String> {
+    let declarations: Declarations = syn::parse_str(input)?;
+    declare(&declarations).map(|out| out.to_string())
+}
+
+#[test]
+fn same_as_attribute() {
+    let declared = expand(
+        "d_f = other_crate::f(x: f64, y: &[f64]) -> f64, Reverse, PerInput(Active, Duplicated), Active",
+    )
+    .unwrap();
+    let config: DiffConfig =
+        syn::parse_str("d_f, Reverse, PerInput(Active, Duplicated), Active, false").unwrap();
+    let primal: syn::ItemFn =
+        syn::parse_str("fn f(x: f64, y: &[f64]) -> f64 { x * y[0] }").unwrap();
+    let attached = generate(config, &primal)
+        .unwrap()
+        .derivative_tokens()
+        .to_string();
+    // Only the shim differs, it calls the given path and has it in its name.
+    assert!(declared.contains("other_crate :: f (x , y)"));
+    assert_eq!(
+        declared
+            .replace("other_crate :: f (x , y)", "f (x , y)")
+            .replace("__enzyme_primal_other_crate__f", "__enzyme_primal_f")
+            .replace("[u8 ; 95usize]", "[u8 ; 82usize]"),
+        attached
+    );
+    assert!(!declared.contains("fn f ("));
+}
+
+#[test]
+fn shared_shim() {
+    let declared = expand(
+        "pub d_f = m::f(x: f64) -> f64, Reverse, All(Active), Active;
+         pub d_f_fwd = m::f(x: f64) -> f64, Forward(1), All(Duplicated), Gradient;",
+    )
+    .unwrap();
+    assert_eq!(declared.matches("fn __enzyme_primal_m__f").count(), 1);
+    assert!(declared.contains("pub fn d_f ("));
+    assert!(declared.contains("pub fn d_f_fwd ("));
+
+    let err = expand(
+        "d_f = m::f(x: f64) -> f64, Reverse, All(Active), Active; d_g = m::f(x: f32) -> f32, Reverse, All(Active), Active",
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains("same signature"));
+
+    // Functions of the same name in different modules get their own shims.
+    let declared = expand(
+        "d_f = a::f(x: f64) -> f64, Reverse, All(Active), Active; d_g = b::f(x: f64) -> f64, Reverse, All(Active), Active",
+    )
+    .unwrap();
+    assert!(declared.contains("fn __enzyme_primal_a__f"));
+    assert!(declared.contains("fn __enzyme_primal_b__f"));
+    let err = expand("d_f = m::f(x: f64) -> f64, Sideways, All(Active), Active").unwrap_err();
+    assert!(err.to_string().contains("Forward"));
+}
@@ -0,0 +1,47 @@
+use autodiff_codegen::differentiable::derive;
+
+fn expand(item: &str) -> syn::Result<String> {
+    let input: syn::DeriveInput = syn::parse_str(item)?;
+    derive(&input).map(|out| out.to_string())
+}
+
+#[test]
+fn tangent_struct() {
+    let out = expand("#[repr(C)] pub struct Model { pub w: [f64; 3], n: usize }").unwrap();
+    assert!(out.contains("pub struct ModelTangent { pub w : < [f64 ; 3] as :: autodiff_runtime :: Differentiable > :: Tangent , n : < usize as :: autodiff_runtime :: Differentiable > :: Tangent }"));
+    assert!(out.contains("unsafe impl :: autodiff_runtime :: Differentiable for Model { type Tangent = ModelTangent ; }"));
+    assert!(out.contains("impl :: autodiff_runtime :: Tangent for ModelTangent"));
+}
+
+#[test]
+fn tuple_struct() {
+    let out = expand("#[repr(C)] struct Pair(f64, f32);").unwrap();
+    assert!(out.contains("struct PairTangent (< f64 as"));
+    assert!(out.contains("Self (:: autodiff_runtime :: Tangent :: zero () , :: autodiff_runtime :: Tangent :: zero ())"));
+}
+
+#[test]
+fn errors() {
+    let err = |item: &str| expand(item).unwrap_err().to_string();
+    assert!(err("struct Model { w: f64 }").contains("#[repr(C)]"));
+    assert!(err("#[repr(C)] enum Model { A }").contains("only be derived for structs"));
+    assert!(err("#[repr(C)] struct Model;").contains("at least one field"));
+    assert!(err("#[repr(C)] struct Model<T> { w: T }").contains("generic"));
+    // Other reprs don't count.
+    assert!(err("#[repr(transparent)] struct Model { w: f64 }").contains("#[repr(C)]"));
+    assert!(expand("#[repr(C, align(8))] struct Model { w: f64 }").is_ok());
+}
+
+#[test]
+fn type_tree() {
+    use autodiff_codegen::type_tree::derive;
+    let input: syn::DeriveInput = syn::parse_str("#[repr(C)] struct Pair(f64, u32);").unwrap();
+    let out = derive(&input).unwrap().to_string();
+    assert!(out.contains("unsafe impl :: autodiff_runtime :: HasTypeTree for Pair"));
+    assert!(out.contains(". field (:: core :: mem :: offset_of ! (Self , 1) , & < u32 as :: autodiff_runtime :: HasTypeTree > :: TREE ,)"));
+    let input: syn::DeriveInput = syn::parse_str("struct Pair(f64, u32);").unwrap();
+    assert!(derive(&input)
+        .unwrap_err()
+        .to_string()
+        .contains("#[repr(C)]"));
+}
@@ -0,0 +1,603 @@
+use autodiff_codegen::{generate, DiffConfig, Generated};
+use quote::ToTokens;
+
+fn gen(config: &str, primal: &str) -> syn::Result<Generated> {
+    let config: DiffConfig = syn::parse_str(config)?;
+    let primal: syn::ItemFn = syn::parse_str(primal)?;
+    generate(config, &primal)
+}
+
+fn field_names(generated: &Generated) -> Vec<String> {
+    let ret = generated.ret_struct.as_ref().expect("a return struct");
+    ret.fields
+        .iter()
+        .map(|f| f.ident.as_ref().unwrap().to_string())
+        .collect()
+}
+
+#[test]
+fn reverse_active() {
+    let out = gen(
+        "d_f, Reverse, PerInput(Active, Duplicated), Active, false",
+        "fn f(x: f64, y: &f64) -> f64 { x * *y }",
+    )
+    .unwrap();
+    let decl = out.declaration.sig.to_token_stream().to_string();
+    assert_eq!(
+        decl,
+        "fn d_f (x : f64 , x : f64 , y : & f64 , y : & mut f64) -> d_f_ret"
+    );
+    assert_eq!(field_names(&out), ["primary_ret", "primary_grad", "x0"]);
+    assert!(out.shim.is_some());
+}
+
+#[test]
+fn vector_forward() {
+    let out = gen(
+        "Forward(2), All(Duplicated), Gradient",
+        "fn f(x: f64) -> f64 { x }",
+    )
+    .unwrap();
+    assert_eq!(out.declaration.sig.ident, "d_f");
+    assert_eq!(field_names(&out), ["primary_grad0", "primary_grad1"]);
+}
+
+#[test]
+fn forward_keeps_primal_output() {
+    let out = gen(
+        "d_f, Forward(1), All(Duplicated), Active",
+        "fn f(x: f64) -> f64 { x }",
+    )
+    .unwrap();
+    // Unlike in reverse mode, the declaration keeps the return type of the primal.
+    assert_eq!(
+        out.declaration.sig.output.to_token_stream().to_string(),
+        "-> f64"
+    );
+    assert_eq!(field_names(&out), ["primary_ret", "primary_grad"]);
+}
+
+#[test]
+fn only_innermost_generates_shim() {
+    let out = gen(
+        "d_f, Reverse, All(Active), Active, false",
+        "#[differentiate_ext(d_f2, Reverse, All(Active), Gradient, false)] fn f(x: f64) -> f64 { x }",
+    )
+    .unwrap();
+    assert!(out.shim.is_none());
+}
+
+#[test]
+fn errors() {
+    let collision = gen(
+        "d_f, Reverse, All(Active), Active, false",
+        "#[differentiate_ext(d_f, Reverse, All(Active), Gradient, false)] fn f(x: f64) -> f64 { x }",
+    );
+    assert!(collision.is_err());
+    let wrong_count = gen(
+        "d_f, Reverse, PerInput(Active), None, false",
+        "fn f(x: f64, y: f64) {}",
+    );
+    assert!(wrong_count.is_err());
+    let active_ref = gen("d_f, Reverse, All(Active), None, false", "fn f(x: &f64) {}");
+    assert!(active_ref.is_err());
+}
+
+#[test]
+fn ret_struct_collisions() {
+    let shared_ret = gen(
+        "d_f, Reverse, All(Active), Active, false, ret = \"Grad\"",
+        "#[differentiate_ext(d_g, Reverse, All(Active), Active, false, ret = \"Grad\")] fn f(x: f64, y: f64) -> f64 { x * y }",
+    );
+    assert!(shared_ret.is_err());
+    // The sibling returns the primal value only, so there is no second struct.
+    let single_ret = gen(
+        "d_f, Reverse, All(Active), Active, false, ret = \"Grad\"",
+        "#[differentiate_ext(d_g, Reverse, All(Constant), Constant, false, ret = \"Grad\")] fn f(x: f64, y: f64) -> f64 { x * y }",
+    );
+    single_ret.unwrap();
+    let shared_field = gen(
+        "d_f, Reverse, All(Active), None, false, field = \"grad\"",
+        "fn f(x: f64, y: f64) {}",
+    );
+    assert!(shared_field.is_err());
+    let named_fields = gen(
+        "d_f, Reverse, All(Active), None, false, field = \"d_{arg}\"",
+        "fn f(x: f64, y: f64) {}",
+    )
+    .unwrap();
+    assert_eq!(field_names(&named_fields), ["d_x", "d_y"]);
+}
+
+#[test]
+fn documented() {
+    let out = gen(
+        "d_f, Reverse, PerInput(Active, Duplicated), Active, false",
+        "fn f(x: f64, y: &f64) -> f64 { x * *y }",
+    )
+    .unwrap();
+    let explanation = out.explanation();
+    assert!(explanation.contains("Reverse mode derivative of `f`"));
+    assert!(explanation.contains(
+        "- `x: f64` (Active): followed by a scalar factor, ∂f/∂x times that factor is returned in `x0`."
+    ));
+    assert!(explanation
+        .contains("- `primary_grad`: The gradient (adjoint) of the return value of `f`."));
+
+    let out = gen(
+        "Forward(2), All(Duplicated), Gradient",
+        "fn f(x: f64) -> f64 { x }",
+    )
+    .unwrap();
+    assert!(out
+        .explanation()
+        .contains("- `primary_grad1`: The tangent of the return value in direction 1."));
+}
+
+#[test]
+fn array_layout() {
+    let out = gen(
+        "Forward(4), All(Duplicated), Active, layout = array",
+        "pub fn f(x: f64, y: &f64) -> f64 { x * *y }",
+    )
+    .unwrap();
+    let wrapper = out.wrapper.as_ref().expect("a wrapper");
+    assert_eq!(
+        wrapper.sig.to_token_stream().to_string(),
+        "unsafe fn d_f (x : f64 , d_x : [f64 ; 4usize] , y : & f64 , d_y : & [f64 ; 4usize]) -> f64"
+    );
+    assert_eq!(out.declaration.sig.ident, "__enzyme_raw_d_f");
+    assert_eq!(out.declaration.sig.inputs.len(), 10);
+    assert_eq!(field_names(&out), ["primary_ret", "primary_grad"]);
+
+    let rev = gen(
+        "Reverse, All(Active), Active, false, layout = array",
+        "fn f(x: f64) -> f64 { x }",
+    );
+    assert!(rev.is_err());
+}
+
+#[test]
+fn array_algebra() {
+    let out = gen(
+        "d_f, Forward(4), All(Duplicated), Active, layout = array, algebra",
+        "fn f(x: f64) -> f64 { x }",
+    )
+    .unwrap();
+    let impls: Vec<String> = out
+        .ret_impls
+        .iter()
+        .map(|i| i.to_token_stream().to_string())
+        .collect();
+    assert_eq!(impls.len(), 3);
+    assert!(impls[0].contains("primary_ret : self . primary_ret + rhs . primary_ret"));
+    assert!(impls[1].contains("for (__l0 , __r0) in (self . primary_grad) . iter_mut () . zip (rhs . primary_grad) { * __l0 += __r0 ; }"));
+    assert!(impls[2].contains("primary_grad : self . primary_grad . map (| __x0 | __x0 * rhs)"));
+    assert!(impls[2].contains("f64 : :: core :: ops :: Mul < __S , Output = f64 >"));
+}
+
+#[test]
+fn runtime_widths() {
+    let out = gen(
+        "Forward(dyn), PerInput(Duplicated, Constant), Gradient",
+        "fn f(x: &mut f32, n: usize) -> f32 { *x }",
+    )
+    .unwrap();
+    assert_eq!(
+        out.declaration.sig.to_token_stream().to_string(),
+        "fn d_f (x : & mut f32 , d_x : * mut f32 , n : usize , d_ret : * mut f32 , width : usize)"
+    );
+    assert!(out.ret_struct.is_none() && out.wrapper.is_none());
+
+    let out = gen(
+        "Forward(consts::WIDTH), All(Duplicated), Active",
+        "fn f(x: f32) -> f32 { x }",
+    )
+    .unwrap();
+    let wrapper = out.wrapper.as_ref().expect("a wrapper");
+    assert_eq!(
+        wrapper.sig.to_token_stream().to_string(),
+        "unsafe fn d_f (x : f32 , d_x : [f32 ; consts :: WIDTH]) -> d_f_ret"
+    );
+    assert_eq!(field_names(&out), ["primary_ret", "primary_grad"]);
+}
+
+#[test]
+fn arrays_by_value() {
+    let out = gen(
+        "Reverse, All(Active), Active, false",
+        "fn f(x: [f64; 3]) -> [f64; 3] { x }",
+    )
+    .unwrap();
+    let shim = out.shim.as_ref().unwrap();
+    assert_eq!(
+        shim.sig.to_token_stream().to_string(),
+        "unsafe extern \"C\" fn __enzyme_primal_f (x : __enzyme_array_f < f64 , { 3 } >) -> __enzyme_array_f < f64 , { 3 } >"
+    );
+    assert!(out.array_struct.is_some());
+    let wrapper = out.wrapper.as_ref().expect("a wrapper");
+    assert_eq!(
+        wrapper.sig.to_token_stream().to_string(),
+        "unsafe fn d_f (x : [f64 ; 3] , d_x : [f64 ; 3]) -> d_f_ret"
+    );
+    assert_eq!(out.declaration.sig.ident, "__enzyme_raw_d_f");
+    assert_eq!(field_names(&out), ["primary_ret", "primary_grad", "x0"]);
+    let generic = gen(
+        "d_f, Reverse, All(Active), Active, false",
+        "fn f<const N: usize>(x: [f64; N]) -> f64 { x[0] }",
+    );
+    let msg = generic.err().unwrap().to_string();
+    assert!(msg.starts_with("Functions which are generic over array lengths"));
+}
+
+#[test]
+fn tangent_shadows() {
+    let out = gen(
+        "d_f, Reverse, PerInput(Duplicated, Duplicated), Constant, false, tangent = [Model]",
+        "fn f(m: &Model, w: &[f64; 2]) -> f64 { m.x * w[0] }",
+    )
+    .unwrap();
+    let decl = out.declaration.sig.to_token_stream().to_string();
+    assert_eq!(
+        decl,
+        "fn d_f (m : & Model , m : & mut < Model as :: autodiff_runtime :: Differentiable > :: Tangent , w : & [f64 ; 2] , w : & mut [f64 ; 2]) -> f64"
+    );
+    let out = gen(
+        "d_f, Forward(2), All(Duplicated), Gradient, tangent = [Model]",
+        "fn f(m: *const [Model; 3]) -> f64 { 0.0 }",
+    )
+    .unwrap();
+    let decl = out.declaration.sig.to_token_stream().to_string();
+    assert!(decl.contains(
+        "d_m_1 : * const [< Model as :: autodiff_runtime :: Differentiable > :: Tangent ; 3]"
+    ));
+    // Without the option, shadows keep the type of their primal.
+    let out = gen(
+        "d_f, Reverse, All(Duplicated), Constant, false",
+        "fn f(m: &geo::Model, x: &f64) -> f64 { 0.0 }",
+    )
+    .unwrap();
+    let decl = out.declaration.sig.to_token_stream().to_string();
+    assert!(decl.contains("m : & mut geo :: Model , x : & f64 , x : & mut f64"));
+}
+
+#[test]
+fn buffers() {
+    let out = gen(
+        "d_f, Reverse, PerInput(Duplicated, Constant, Duplicated, Duplicated), None, false, buffer = [Matrix]",
+        "fn f(x: &Vec<f64>, n: usize, m: &Matrix, y: &mut [f64]) {}",
+    )
+    .unwrap();
+    let decl = out.declaration.sig.to_token_stream().to_string();
+    assert_eq!(
+        decl,
+        "fn __enzyme_raw_d_f (x : * const f64 , d_x : * mut f64 , x_len : usize , n : usize , m : * const < Matrix as :: autodiff_runtime :: AdBuffer > :: Elem , d_m : * mut < Matrix as :: autodiff_runtime :: AdBuffer > :: Elem , m_len : usize , y : * mut f64 , d_y : * mut f64 , y_len : usize)"
+    );
+    let wrapper = out.wrapper.unwrap();
+    let wrapper_sig = wrapper.sig.to_token_stream().to_string();
+    assert_eq!(
+        wrapper_sig,
+        "unsafe fn d_f (x : & Vec < f64 > , d_x : & mut Vec < f64 > , n : usize , m : & Matrix , d_m : & mut Matrix , y : & mut [f64] , d_y : & mut [f64])"
+    );
+    let body = wrapper.block.to_token_stream().to_string();
+    assert!(body.contains("assert_eq ! (x . capacity () , x_len ,"));
+    assert!(body.contains(
+        "assert_eq ! (d_y . len () , y_len , \"`d_y` needs as many elements as `y`\") ;"
+    ));
+    let shim = out.shim.unwrap();
+    assert_eq!(
+        shim.sig.to_token_stream().to_string(),
+        "unsafe extern \"C\" fn __enzyme_primal_f (x : * const f64 , x_len : usize , n : usize , m : * const < Matrix as :: autodiff_runtime :: AdBuffer > :: Elem , m_len : usize , y : * mut f64 , y_len : usize)"
+    );
+    let shim_body = shim.block.to_token_stream().to_string();
+    assert!(shim_body
+        .contains("let y = unsafe { :: core :: slice :: from_raw_parts_mut (y , y_len) } ;"));
+    assert!(shim_body.contains("f (& * x , n , & * m , y)"));
+
+    // The primal could grow or replace containers behind mutable references.
+    for primal in ["fn f(x: &mut Vec<f64>) {}", "fn f(x: &mut Box<[f64]>) {}"] {
+        let msg = gen("d_f, Reverse, All(Duplicated), None, false", primal)
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(msg.contains("Please take a mutable slice of its elements instead."));
+    }
+    let listed = gen(
+        "d_f, Reverse, All(Duplicated), None, false, buffer = [Matrix]",
+        "fn f(m: &mut Matrix) {}",
+    );
+    assert!(listed.is_err());
+
+    // Only listed types besides Vec and Box<[T]> are lowered.
+    let out = gen(
+        "d_f, Reverse, All(Duplicated), None, false",
+        "fn f(m: &mut Matrix) {}",
+    )
+    .unwrap();
+    assert!(out.wrapper.is_none());
+
+    let mixed = gen(
+        "d_f, Forward(2), All(Duplicated), Gradient, layout = array",
+        "fn f(x: &Vec<f64>) -> f64 { 0.0 }",
+    );
+    assert!(mixed.is_err());
+    let stacked = gen(
+        "d_g, Reverse, All(Duplicated), None, false, buffer = [Matrix]",
+        "#[differentiate_ext(d_f, Reverse, All(Duplicated), None, false)] fn f(m: &Matrix) {}",
+    );
+    assert!(stacked.is_err());
+}
+
+#[test]
+fn parallel_buffers() {
+    let out = gen(
+        "d_f, Reverse, PerInput(Duplicated), Active, true",
+        "fn f(x: &[f64]) -> f64 { x[0] }",
+    )
+    .unwrap();
+    // The shadow holds atomics, so its pointer does too.
+    let decl = out.declaration.sig.to_token_stream().to_string();
+    assert!(decl.contains(
+        "x : * const f64 , d_x : * const :: core :: sync :: atomic :: AtomicU64 , x_len : usize"
+    ));
+    let wrapper = out.wrapper.unwrap().sig.to_token_stream().to_string();
+    assert!(wrapper.contains("d_x : & [:: core :: sync :: atomic :: AtomicU64]"));
+}
+
+#[test]
+fn checkpoints() {
+    let out = gen(
+        "d_f, Reverse, All(Active), Active, false, checkpoint = [helper, inner::step]",
+        "fn f(x: f64) -> f64 { helper(inner::step(x)) }",
+    )
+    .unwrap();
+    let meta = out.metadata.to_token_stream().to_string();
+    assert!(meta.contains(r"checkpoint=helper,inner::step\n"));
+    let forward = gen(
+        "d_f, Forward(1), All(Duplicated), Gradient, checkpoint = [helper]",
+        "fn f(x: f64) -> f64 { helper(x) }",
+    );
+    assert!(forward.is_err());
+}
+
+#[test]
+fn backend_options() {
+    let out = gen(
+        "d_f, Reverse, All(Active), Active, false, options(strong_zero, free_memory = false)",
+        "fn f(x: f64) -> f64 { x }",
+    )
+    .unwrap();
+    let meta = out.metadata.to_token_stream().to_string();
+    assert!(meta.contains(r"parallel_context=false\nstrong_zero=true\nfree_memory=false\n\0"));
+
+    let unknown = gen(
+        "d_f, Forward(1), All(Duplicated), Gradient, options(strong_zeros = true)",
+        "fn f(x: f64) -> f64 { x }",
+    );
+    let msg = unknown.err().unwrap().to_string();
+    assert!(msg.contains("Unknown backend option `strong_zeros`! Known are: strong_zero,"));
+    let twice = gen(
+        "d_f, Reverse, All(Active), Active, false, options(loose_types, loose_types = false)",
+        "fn f(x: f64) -> f64 { x }",
+    );
+    assert!(twice.is_err());
+}
+
+#[test]
+fn type_trees() {
+    let out = gen(
+        "d_f, Reverse, PerInput(Duplicated, Constant), Active, false, type_tree",
+        "fn f(x: &Vec<f64>, n: u32) -> f64 { x[0] * n as f64 }",
+    )
+    .unwrap();
+    let meta = out.metadata.to_token_stream().to_string();
+    assert!(meta.contains(r"type_tree=__enzyme_typetree_d_f\n"));
+    let items = &out.type_tree;
+    let items = quote::quote! { #(#items)* }.to_string();
+    // The container is described like the shim sees it, as pointer and length.
+    assert!(items.contains(". param (& < * const f64 as :: autodiff_runtime :: HasTypeTree > :: TREE) . param (& < usize as"));
+    assert!(items.contains(
+        ". param (& < u32 as :: autodiff_runtime :: HasTypeTree > :: TREE) . ret (& < f64 as"
+    ));
+    assert!(items.contains(
+        "# [no_mangle] static __enzyme_typetree_d_f : [u8 ; __enzyme_typetree_text_d_f . len ()]"
+    ));
+
+    let off = gen(
+        "d_f, Reverse, All(Active), Active, false",
+        "fn f(x: f64) -> f64 { x }",
+    )
+    .unwrap();
+    assert!(off.type_tree.is_empty());
+    assert!(!off
+        .metadata
+        .to_token_stream()
+        .to_string()
+        .contains("type_tree"));
+}
+
+#[test]
+fn callbacks() {
+    let out = gen(
+        "d_euler, Reverse, PerInput(Duplicated, Duplicated), None, false, callback(rhs = ode::d_rhs)",
+        "fn euler(rhs: extern \"C\" fn(f64) -> f64, y: &mut f64) { *y += rhs(*y) }",
+    )
+    .unwrap();
+    let decl = out.declaration.to_token_stream().to_string();
+    assert!(decl.contains(
+        "rhs : extern \"C\" fn (f64) -> f64 , rhs : ode :: __enzyme_fn_d_rhs , y : & mut f64"
+    ));
+    let meta = out.metadata.to_token_stream().to_string();
+    assert!(meta.contains(r"callback=rhs=ode::d_rhs\n"));
+    let items = &out.callbacks;
+    let items = quote::quote! { #(#items)* }.to_string();
+    assert!(items.contains("const _ : fn (ode :: __enzyme_primal_fn_d_rhs) -> extern \"C\" fn (f64) -> f64 = | f | f ;"));
+    // Each derivative can be the shadow of a callback itself.
+    assert!(items.contains("type __enzyme_fn_d_euler = unsafe extern \"C\" fn (extern \"C\" fn (f64) -> f64 , ode :: __enzyme_fn_d_rhs , & mut f64 , & mut f64) ;"));
+    assert!(items.contains(
+        "type __enzyme_primal_fn_d_euler = fn (extern \"C\" fn (f64) -> f64 , & mut f64) ;"
+    ));
+
+    let fwd = gen(
+        "d_f, Forward(2), PerInput(Duplicated, Constant), Active, callback(g = d_g)",
+        "fn f(g: fn(f64) -> f64, x: f64) -> f64 { g(x) }",
+    )
+    .unwrap();
+    let decl = fwd.declaration.to_token_stream().to_string();
+    assert!(decl.contains("d_g_0 : __enzyme_fn_d_g , d_g_1 : __enzyme_fn_d_g"));
+
+    let err = |config: &str| {
+        gen(config, "fn f(g: fn(f64) -> f64, x: &f64) -> f64 { g(*x) }")
+            .err()
+            .unwrap()
+            .to_string()
+    };
+    assert!(
+        err("d_f, Reverse, PerInput(Duplicated, Constant), Active, false")
+            .contains("please name it with `callback(g = d_callee)`")
+    );
+    assert!(
+        err("d_f, Reverse, PerInput(Constant, Constant), Active, false, callback(g = d_g)")
+            .contains("isn't Duplicated")
+    );
+    assert!(
+        err("d_f, Reverse, PerInput(Constant, Duplicated), Active, false, callback(x = d_g)")
+            .contains("isn't a function pointer parameter")
+    );
+    assert!(
+        err("d_f, Reverse, All(Constant), Active, false, callback(g = d_g, g = d_h)")
+            .contains("listed twice")
+    );
+}
+
+#[test]
+fn foreign() {
+    let gen_foreign = |config: &str, primal: &str| {
+        let config: DiffConfig = syn::parse_str(config)?;
+        let primal: syn::ForeignItemFn = syn::parse_str(primal)?;
+        autodiff_codegen::generate_foreign(config, &primal)
+    };
+    let decl = gen_foreign(
+        "d_scale, Reverse, PerInput(Active, Constant), None, false",
+        "fn scale(x: f64, s: f64) -> f64;",
+    )
+    .unwrap();
+    let decl = decl.to_token_stream().to_string();
+    assert!(decl.ends_with("fn d_scale (x : f64 , x : f64 , s : f64) -> f64 ;"));
+
+    let err = |config: &str, primal: &str| gen_foreign(config, primal).err().unwrap().to_string();
+    assert!(err(
+        "d_f, Reverse, All(Active), Active, false",
+        "fn f(x: f64, y: f64) -> f64;"
+    )
+    .contains("would return a struct"));
+    assert!(err(
+        "d_f, Reverse, All(Duplicated), None, false",
+        "fn f(x: &Vec<f64>);"
+    )
+    .contains("would need a wrapper"));
+    assert!(err(
+        "d_f, Reverse, All(Active), None, false, type_tree",
+        "fn f(x: f64) -> f64;"
+    )
+    .contains("can't be emitted in extern blocks"));
+    assert!(err(
+        "d_f, Reverse, All(Active), None, false",
+        "fn f(x: f64, ...) -> f64;"
+    )
+    .contains("Variadic"));
+}
+
+#[test]
+fn extern_block() {
+    let gen_block = |config: &str, block: &str| {
+        let config: DiffConfig = syn::parse_str(config)?;
+        let block: syn::ItemForeignMod = syn::parse_str(block)?;
+        autodiff_codegen::generate_extern_block(config, &block).map(|out| out.to_string())
+    };
+    let out = gen_block(
+        "d_f, Reverse, All(Active), Active, false",
+        "extern \"C\" { #[link_name = \"c_f\"] fn f(x: f64, y: f64) -> f64; }",
+    )
+    .unwrap();
+    assert!(out.contains("fn d_f (x : f64 , x : f64 , y : f64 , y : f64) -> d_f_ret ;"));
+    assert!(out.contains("struct d_f_ret"));
+    // The backend differentiates the foreign symbol itself.
+    assert!(out.contains("primal=c_f\\n"));
+
+    let err = gen_block(
+        "d_f, Reverse, All(Active), None, false",
+        "extern \"C\" { fn f(x: f64) -> f64; fn g(x: f64) -> f64; }",
+    )
+    .err()
+    .unwrap()
+    .to_string();
+    assert!(err.contains("exactly one function"));
+}
+
+#[test]
+fn higher_order() {
+    let primal =
+        "#[differentiate_ext(dd_cube, Reverse, PerInput(Active, Constant), None, of = d_cube)]
+        #[differentiate_ext(d_cube, Reverse, All(Active), None)]
+        fn cube(x: f64) -> f64 { x * x * x }";
+    let out = gen(
+        "ddd_cube, Reverse, PerInput(Active, Constant, Constant), None, of = dd_cube",
+        primal,
+    )
+    .unwrap();
+    let sig = out.declaration.sig.to_token_stream().to_string();
+    assert_eq!(
+        sig,
+        "fn ddd_cube (x : f64 , x : f64 , d_x : f64 , d_d_x : f64) -> f64"
+    );
+    // The shim calls the derivative below, with the shadows renamed.
+    let shim = out.shim.unwrap().to_token_stream().to_string();
+    assert!(shim.contains("fn __enzyme_primal_dd_cube (x : f64 , d_x : f64 , d_d_x : f64) -> f64 { unsafe { dd_cube (x , d_x , d_d_x) } }"));
+    let meta = out.metadata.to_token_stream().to_string();
+    assert!(meta.contains(r"primal=__enzyme_primal_dd_cube\n"));
+    assert!(meta.contains(r"of=dd_cube\n"));
+    // The original primal is still emitted.
+    assert_eq!(out.primal.sig.ident, "cube");
+
+    // Derivatives of the same derivative share one shim.
+    let primal =
+        "#[differentiate_ext(d_cube_fwd, Forward(1), All(Duplicated), Gradient, of = d_cube)]
+        #[differentiate_ext(d_cube, Reverse, All(Active), None)]
+        fn cube(x: f64) -> f64 { x * x * x }";
+    let out = gen(
+        "d_cube_rev, Reverse, PerInput(Active, Constant), None, of = d_cube",
+        primal,
+    )
+    .unwrap();
+    assert!(out.shim.is_none());
+
+    let missing = gen(
+        "dd_f, Reverse, All(Active), None, of = d_f",
+        "fn f(x: f64) -> f64 { x }",
+    );
+    assert!(missing
+        .err()
+        .unwrap()
+        .to_string()
+        .contains("No derivative `d_f` found"));
+
+    // Derivatives of derivatives can collide with the ones of the primal as well.
+    let err = |config: &str, primal: &str| gen(config, primal).err().unwrap().to_string();
+    let primal = "#[differentiate_ext(d_cube, Reverse, All(Active), None)]
+        #[differentiate_ext(dd_cube, Forward(1), All(Duplicated), Gradient)]
+        fn cube(x: f64) -> f64 { x * x * x }";
+    assert!(err(
+        "dd_cube, Reverse, PerInput(Active, Constant), None, of = d_cube",
+        primal
+    )
+    .contains("used by multiple differentiate_ext attributes on `cube`"));
+    let primal = "#[differentiate_ext(d_cube, Reverse, All(Active), Active)]
+        #[differentiate_ext(d_sq, Reverse, All(Active), Active, ret = \"grads\")]
+        fn cube(x: f64) -> f64 { x * x * x }";
+    assert!(err(
+        "dd_cube, Reverse, PerInput(Active, Constant), Active, of = d_cube, ret = \"grads\"",
+        primal
+    )
+    .contains("would both return a struct named `grads`"));
+}
@@ -0,0 +1,69 @@
+use std::path::Path;
+
+use autodiff_codegen::header::header;
+use autodiff_codegen::scan::{repr_c_structs, scan_file};
+
+fn rev_header() -> String {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("../tests/rev.rs");
+    let found = scan_file(&path).unwrap();
+    let structs = repr_c_structs(&path).unwrap();
+    header(&found, &structs, "REV_H").unwrap()
+}
+
+#[test]
+fn prototypes() {
+    let h = rev_header();
+    assert!(h.starts_with("/* Generated by autodiff-codegen"));
+    assert!(h.contains("#ifndef REV_H\n#define REV_H\n"));
+    assert!(h.contains("void __enzyme_primal_a(float *x, float y);\n"));
+    assert!(h.contains("float __enzyme_primal_f(const float *x, float y);\n"));
+    // Shadows get their own name, and atomic shadows are passed as their integer equivalent.
+    assert!(h.contains(
+        "d_g_ret d_g(const double *x, const uint64_t *d_x, float *y, const uint32_t *d_y, double z, double d_z);\n"
+    ));
+    // Prefixed symbols.
+    assert!(h.contains("d_q_ret my_prefix_d_q(double x, double d_x);\n"));
+}
+
+#[test]
+fn structs() {
+    let h = rev_header();
+    assert!(h.contains("typedef struct Pair Pair;\n"));
+    assert!(h.contains("struct Pair {\n    double a;\n    double b;\n};\n"));
+    assert!(h.contains(
+        "struct d_d_ret {\n    float primary_ret;\n    float primary_grad;\n    float x0;\n};\n"
+    ));
+    // Pair has to be defined before the return struct which contains it.
+    assert!(h.find("struct Pair {").unwrap() < h.find("struct d_p_ret {").unwrap());
+    assert!(h.contains("Pair __enzyme_primal_p(Pair pair, double scale);\n"));
+}
+
+#[test]
+fn tangent_shadows() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("../tests/model.rs");
+    let found = scan_file(&path).unwrap();
+    let structs = repr_c_structs(&path).unwrap();
+    let h = header(&found, &structs, "MODEL_H").unwrap();
+    // Unlisted structs keep shadows of their own type.
+    assert!(h.contains("d_norm_ret d_norm(const Point *p, Point *d_p);\n"));
+    assert!(h.contains("struct Point {\n    double x;\n    double y;\n};\n"));
+    // Tangents have the layout of their primal type.
+    assert!(h.contains("double d_loss(const Model *model, Model *d_model, const double *x);\n"));
+}
+
+#[test]
+fn unsupported_types() {
+    let primal: syn::ItemFn = syn::parse_str("fn f(x: &dyn Model, y: f64) -> f64 { y }").unwrap();
+    let config: autodiff_codegen::DiffConfig =
+        syn::parse_str("d_f, Reverse, PerInput(Constant, Active), Active, false").unwrap();
+    let generated = autodiff_codegen::generate(config.clone(), &primal).unwrap();
+    let found = autodiff_codegen::scan::Found {
+        file: "lib.rs".into(),
+        line: 1,
+        config,
+        generated,
+        params: vec![],
+    };
+    let err = header(&[found], &[], "H").err().unwrap();
+    assert!(err.to_string().contains("not FFI-safe"));
+}
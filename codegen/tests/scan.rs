@@ -0,0 +1,63 @@
+use std::path::Path;
+
+use autodiff_codegen::scan::scan_file;
+
+#[test]
+fn scan_rev_tests() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("../tests/rev.rs");
+    let found = scan_file(&path).unwrap();
+    let names: Vec<String> = found
+        .iter()
+        .map(|f| f.generated.declaration.sig.ident.to_string())
+        .collect();
+    assert!(names.starts_with(&["d_a2".to_owned(), "d_a3".to_owned(), "d_a1".to_owned()]));
+    // Unnamed derivatives get their names from the naming template.
+    assert!(names.contains(&"s_grad_fwd4".to_owned()));
+
+    let d_a1 = &found[2];
+    assert_eq!(d_a1.line, 8);
+    assert_eq!(d_a1.params.len(), 2);
+    assert_eq!(d_a1.params[1].activity, "Active");
+    assert!(d_a1
+        .config
+        .to_string()
+        .contains("PerInput(Gradient, Active)"));
+    // Only the innermost attribute generates the shim.
+    assert!(found[0].generated.shim.is_none());
+    assert!(d_a1.generated.shim.is_some());
+}
+
+#[test]
+fn scan_foreign_and_declared() {
+    let names = |file: &str| -> Vec<String> {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../tests")
+            .join(file);
+        scan_file(&path)
+            .unwrap()
+            .iter()
+            .map(|f| f.generated.declaration.sig.ident.to_string())
+            .collect()
+    };
+    assert_eq!(
+        names("foreign.rs"),
+        ["d_norm2", "d_scale_s", "d_scale", "d_hypot"]
+    );
+    assert_eq!(names("declare.rs"), ["d_energy", "d_energy_fwd", "d_sin"]);
+
+    // The backend differentiates foreign functions themselves, there is no shim.
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("../tests/foreign.rs");
+    assert!(scan_file(&path)
+        .unwrap()
+        .iter()
+        .all(|f| f.generated.shim.is_none()));
+
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("../tests/declare.rs");
+    let found = scan_file(&path).unwrap();
+    // Declared derivatives are found at the path of their function.
+    assert_eq!(found[0].line, 16);
+    assert_eq!(found[0].generated.primal.sig.ident, "energy");
+    // Only the last entry of the same function generates the shim.
+    assert!(found[0].generated.shim.is_none());
+    assert!(found[1].generated.shim.is_some());
+}
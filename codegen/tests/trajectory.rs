@@ -0,0 +1,49 @@
+use autodiff_codegen::trajectory::trajectory;
+use quote::ToTokens;
+
+fn driver(derivative: &str, primal: &str) -> syn::Result<String> {
+    let derivative: syn::Ident = syn::parse_str(derivative)?;
+    let primal: syn::ItemFn = syn::parse_str(primal)?;
+    trajectory(&derivative, &primal).map(|driver| driver.sig.to_token_stream().to_string())
+}
+
+#[test]
+fn signature() {
+    let sig = driver(
+        "d_step",
+        "#[differentiate_ext(d_other, Reverse, All(Duplicated), None, false)]
+        #[differentiate_ext(d_step, Reverse, PerInput(Duplicated, Constant), None, false)]
+        fn step(state: &mut [f64; 3], dt: f64) {}",
+    )
+    .unwrap();
+    assert_eq!(
+        sig,
+        "unsafe fn step_trajectory_adjoint (state0 : & [f64 ; 3] , d_state : & mut [f64 ; 3] , n_steps : usize , snapshots : usize , dt : f64)"
+    );
+}
+
+#[test]
+fn errors() {
+    let err = |derivative: &str, primal: &str| driver(derivative, primal).unwrap_err().to_string();
+    assert!(err("d_step", "fn step(state: &mut f64) {}").contains("No derivative `d_step`"));
+    assert!(err(
+        "d_step",
+        "#[differentiate_ext(d_step, Forward(1), All(Duplicated), Gradient)] fn step(state: &mut f64) {}",
+    )
+    .contains("reverse mode"));
+    assert!(err(
+        "d_step",
+        "#[differentiate_ext(d_step, Reverse, PerInput(Duplicated), None, false)] fn step(state: &f64) {}",
+    )
+    .contains("&mut State"));
+    assert!(err(
+        "d_step",
+        "#[differentiate_ext(d_step, Reverse, PerInput(Duplicated, Active), None, false)] fn step(state: &mut f64, dt: f64) {}",
+    )
+    .contains("only Duplicated parameter"));
+    assert!(err(
+        "d_step",
+        "#[differentiate_ext(d_step, Reverse, PerInput(Constant, Duplicated), None, false)] fn step(state: &mut f64, v: &mut f64) {}",
+    )
+    .contains("only Duplicated parameter"));
+}
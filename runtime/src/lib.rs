@@ -0,0 +1,348 @@
+//! Traits and helpers for the code generated by the `autodiff` macros.
+//!
+//! Proc-macro crates can only export macros, so everything which the generated code
+//! needs at runtime lives here. Add it next to `autodiff` to your dependencies.
+
+#![doc(html_logo_url = "https://enzyme.mit.edu//logo.svg")]
+
+use std::mem::ManuallyDrop;
+
+pub mod type_tree;
+pub use type_tree::HasTypeTree;
+
+/// Types which can be differentiated with respect to, usually through `#[derive(Differentiable)]`.
+///
+/// Enzyme expects the shadow of a `Duplicated` argument to be a copy of the primal memory,
+/// in which it accumulates the gradients. `Tangent` is the type of that shadow.
+///
+/// # Safety
+///
+/// `Tangent` must have exactly the same size and layout as `Self`,
+/// with the tangent of each float at the offset of that float in `Self`.
+pub unsafe trait Differentiable {
+    type Tangent: Tangent;
+}
+
+/// The shadow of a `Differentiable` type, a vector of `LEN` floats.
+///
+/// Fields which can't be differentiated, like integers, keep their place in the layout,
+/// but don't count towards `LEN` and are ignored by the vector operations.
+pub trait Tangent: Clone + std::fmt::Debug + PartialEq {
+    /// The number of floats, as seen by `flatten_into` and `unflatten`.
+    const LEN: usize;
+    /// A tangent with all floats set to zero, e.g. to reset a shadow before the reverse pass.
+    fn zero() -> Self;
+    /// `self += alpha * x`, element-wise.
+    fn axpy(&mut self, alpha: f64, x: &Self);
+    /// Writes all floats to `out[..Self::LEN]`, in the order of the fields.
+    fn flatten_into(&self, out: &mut [f64]);
+    /// Reads a tangent from `src[..Self::LEN]`, the inverse of `flatten_into`.
+    fn unflatten(src: &[f64]) -> Self;
+    /// All floats in one vector, e.g. for optimizers working on `&[f64]`.
+    fn flatten(&self) -> Vec<f64> {
+        let mut out = vec![0.0; Self::LEN];
+        self.flatten_into(&mut out);
+        out
+    }
+}
+
+macro_rules! float {
+    ($($ty:ty),*) => {$(
+        unsafe impl Differentiable for $ty {
+            type Tangent = $ty;
+        }
+        impl Tangent for $ty {
+            const LEN: usize = 1;
+            fn zero() -> Self {
+                0.0
+            }
+            fn axpy(&mut self, alpha: f64, x: &Self) {
+                *self += (alpha * *x as f64) as $ty;
+            }
+            fn flatten_into(&self, out: &mut [f64]) {
+                out[0] = *self as f64;
+            }
+            fn unflatten(src: &[f64]) -> Self {
+                src[0] as $ty
+            }
+        }
+    )*};
+}
+float!(f32, f64);
+
+// Passive values are copied into the shadow, but never differentiated.
+macro_rules! passive {
+    ($($ty:ty),*) => {$(
+        unsafe impl Differentiable for $ty {
+            type Tangent = $ty;
+        }
+        impl Tangent for $ty {
+            const LEN: usize = 0;
+            fn zero() -> Self {
+                Default::default()
+            }
+            fn axpy(&mut self, _alpha: f64, _x: &Self) {}
+            fn flatten_into(&self, _out: &mut [f64]) {}
+            fn unflatten(_src: &[f64]) -> Self {
+                Default::default()
+            }
+        }
+    )*};
+}
+passive!(i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize, bool, char);
+
+unsafe impl<T: Differentiable, const N: usize> Differentiable for [T; N] {
+    type Tangent = [T::Tangent; N];
+}
+
+impl<T: Tangent, const N: usize> Tangent for [T; N] {
+    const LEN: usize = N * T::LEN;
+    fn zero() -> Self {
+        std::array::from_fn(|_| T::zero())
+    }
+    fn axpy(&mut self, alpha: f64, x: &Self) {
+        for (elem, x) in self.iter_mut().zip(x) {
+            elem.axpy(alpha, x);
+        }
+    }
+    fn flatten_into(&self, out: &mut [f64]) {
+        for (i, elem) in self.iter().enumerate() {
+            elem.flatten_into(&mut out[i * T::LEN..(i + 1) * T::LEN]);
+        }
+    }
+    fn unflatten(src: &[f64]) -> Self {
+        std::array::from_fn(|i| T::unflatten(&src[i * T::LEN..(i + 1) * T::LEN]))
+    }
+}
+
+/// Containers which cross the derivative boundary as a pointer to their elements and a length.
+///
+/// References to such containers are lowered to `*const Elem` / `*mut Elem` and a `usize`
+/// by `differentiate_ext`, the primal shim then rebuilds the container with `view`.
+/// `Vec<T>` and `Box<[T]>` are recognized automatically,
+/// other implementations have to be listed in the `buffer = [..]` option.
+///
+/// # Safety
+///
+/// `as_raw` and `as_raw_mut` must return a pointer to `len` initialized, contiguous elements,
+/// and `view` must turn such a pointer and length back into a container with the same elements.
+///
+/// Only the pointer and the length cross the boundary, so containers which also track the size
+/// of their allocation, like `Vec<T>`, can only be viewed if it holds exactly `len` elements.
+/// Callers of derivatives taking such containers have to uphold this, e.g. with `shrink_to_fit`.
+pub unsafe trait AdBuffer {
+    type Elem: Tangent;
+    fn as_raw(&self) -> (*const Self::Elem, usize);
+    fn as_raw_mut(&mut self) -> (*mut Self::Elem, usize);
+    /// A container of the same shape with all elements zero, e.g. as shadow for the reverse pass.
+    fn zeroed_shadow(&self) -> Self;
+    /// Borrows the `len` elements at `ptr` as container, without taking ownership of them.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` and `len` must come from `as_raw` or `as_raw_mut` of a container which outlives
+    /// the view, and the view must only be used through shared references.
+    /// For `Vec<T>`, the container must also have a capacity of exactly `len`.
+    unsafe fn view(ptr: *mut Self::Elem, len: usize) -> ManuallyDrop<Self>
+    where
+        Self: Sized;
+}
+
+unsafe impl<T: Tangent> AdBuffer for Vec<T> {
+    type Elem = T;
+    fn as_raw(&self) -> (*const T, usize) {
+        (self.as_ptr(), self.len())
+    }
+    fn as_raw_mut(&mut self) -> (*mut T, usize) {
+        (self.as_mut_ptr(), self.len())
+    }
+    fn zeroed_shadow(&self) -> Self {
+        self.iter().map(|_| T::zero()).collect()
+    }
+    unsafe fn view(ptr: *mut T, len: usize) -> ManuallyDrop<Self> {
+        // `from_raw_parts` needs the capacity of the allocation, which the caller guarantees
+        // to be `len`, see the safety section of `AdBuffer`.
+        ManuallyDrop::new(Vec::from_raw_parts(ptr, len, len))
+    }
+}
+
+unsafe impl<T: Tangent> AdBuffer for Box<[T]> {
+    type Elem = T;
+    fn as_raw(&self) -> (*const T, usize) {
+        (self.as_ptr(), self.len())
+    }
+    fn as_raw_mut(&mut self) -> (*mut T, usize) {
+        (self.as_mut_ptr(), self.len())
+    }
+    fn zeroed_shadow(&self) -> Self {
+        self.iter().map(|_| T::zero()).collect()
+    }
+    unsafe fn view(ptr: *mut T, len: usize) -> ManuallyDrop<Self> {
+        ManuallyDrop::new(Box::from_raw(std::ptr::slice_from_raw_parts_mut(ptr, len)))
+    }
+}
+
+/// `C(s + t, s)`, the number of steps which can be reversed with `s` snapshots
+/// if each step is recomputed at most `t` times. Saturates instead of overflowing.
+fn binomial(s: usize, t: usize) -> usize {
+    let mut beta: usize = 1;
+    for i in 1..=s.min(t) {
+        beta = match beta.checked_mul(s + t + 1 - i) {
+            Some(b) => b / i,
+            None => return usize::MAX,
+        };
+    }
+    beta
+}
+
+/// Runs the adjoints of `n_steps` applications of `step` to `state0` in reverse order,
+/// keeping at most `snapshots` states besides `state0` alive (binomial checkpointing, "revolve").
+///
+/// `adjoint` is called once per step, last step first, with the state before that step,
+/// which it may overwrite. Usually it calls the reverse mode derivative of the step function,
+/// which accumulates the adjoint of the state in its shadow.
+/// States which aren't stored are recomputed from the closest snapshot,
+/// so the number of `step` calls grows only logarithmically with fewer snapshots.
+pub fn revolve<S: Clone>(
+    state0: &S,
+    n_steps: usize,
+    snapshots: usize,
+    mut step: impl FnMut(&mut S),
+    mut adjoint: impl FnMut(&mut S),
+) {
+    if n_steps > 0 {
+        reverse(state0.clone(), n_steps, snapshots, &mut step, &mut adjoint);
+    }
+}
+
+/// Reverses the `n` steps following `state`.
+fn reverse<S: Clone>(
+    mut state: S,
+    n: usize,
+    snapshots: usize,
+    step: &mut impl FnMut(&mut S),
+    adjoint: &mut impl FnMut(&mut S),
+) {
+    if n == 1 {
+        return adjoint(&mut state);
+    }
+    if snapshots == 0 {
+        for i in (0..n).rev() {
+            let mut s = state.clone();
+            (0..i).for_each(|_| step(&mut s));
+            adjoint(&mut s);
+        }
+        return;
+    }
+    // The smallest number of repetitions t with which n steps can be reversed,
+    // then the steps after the snapshot get one snapshot less and the ones before one repetition less.
+    let t = (1..).find(|&t| binomial(snapshots, t) >= n).unwrap();
+    let first = n.saturating_sub(binomial(snapshots - 1, t)).max(1);
+    let mut snapshot = state.clone();
+    (0..first).for_each(|_| step(&mut snapshot));
+    reverse(snapshot, n - first, snapshots - 1, step, adjoint);
+    reverse(state, first, snapshots, step, adjoint);
+}
+
+/// Detaches `x` from the gradient: derivatives treat the result as constant.
+///
+/// This is the identity, through `StopGradient::inactive`.
+#[inline(always)]
+pub fn stop_gradient<T: StopGradient>(x: T) -> T {
+    x.inactive()
+}
+
+/// Values which can be passed through an inactive identity function, see `stop_gradient`.
+///
+/// Floats go through helpers which the backend treats as constant, other primitives are never
+/// differentiated, so they are passed on unchanged. Arrays and tuples are handled element-wise.
+#[diagnostic::on_unimplemented(
+    message = "`stop_gradient` doesn't support `{Self}`",
+    note = "stop the gradient of its float fields one by one instead"
+)]
+pub trait StopGradient {
+    /// The identity, through a function which the backend treats as constant.
+    fn inactive(self) -> Self;
+}
+
+// The metadata marking a helper as inactive, like `#[autodiff::inactive]` generates it.
+macro_rules! inactive_identity {
+    ($ty:ty, $name:ident, $meta:ident, $lit:literal) => {
+        #[doc(hidden)]
+        #[no_mangle]
+        #[inline(never)]
+        pub extern "C" fn $name(x: $ty) -> $ty {
+            x
+        }
+
+        #[doc(hidden)]
+        #[allow(non_upper_case_globals)]
+        #[used]
+        #[no_mangle]
+        static $meta: [u8; $lit.len()] = *$lit;
+
+        impl StopGradient for $ty {
+            fn inactive(self) -> Self {
+                $name(self)
+            }
+        }
+    };
+}
+inactive_identity!(
+    f64,
+    __enzyme_stop_gradient_f64,
+    __enzyme_inactive___enzyme_stop_gradient_f64,
+    b"name=__enzyme_stop_gradient_f64\nsymbol=__enzyme_stop_gradient_f64\ninactive=true\n\0"
+);
+inactive_identity!(
+    f32,
+    __enzyme_stop_gradient_f32,
+    __enzyme_inactive___enzyme_stop_gradient_f32,
+    b"name=__enzyme_stop_gradient_f32\nsymbol=__enzyme_stop_gradient_f32\ninactive=true\n\0"
+);
+
+impl<T: StopGradient, const N: usize> StopGradient for [T; N] {
+    fn inactive(self) -> Self {
+        self.map(T::inactive)
+    }
+}
+
+macro_rules! passive_identity {
+    ($($ty:ty),*) => {$(
+        impl StopGradient for $ty {
+            fn inactive(self) -> Self {
+                self
+            }
+        }
+    )*};
+}
+passive_identity!(
+    i8,
+    i16,
+    i32,
+    i64,
+    i128,
+    isize,
+    u8,
+    u16,
+    u32,
+    u64,
+    u128,
+    usize,
+    bool,
+    char,
+    ()
+);
+
+macro_rules! tuples {
+    ($(($($t:ident),+)),*) => {$(
+        impl<$($t: StopGradient),+> StopGradient for ($($t,)+) {
+            #[allow(non_snake_case)]
+            fn inactive(self) -> Self {
+                let ($($t,)+) = self;
+                ($($t.inactive(),)+)
+            }
+        }
+    )*};
+}
+tuples!((A), (A, B), (A, B, C), (A, B, C, D));
@@ -0,0 +1,362 @@
+//! Type trees describe which bytes of a value are floats, integers or pointers.
+//!
+//! Enzyme usually finds this out by type analysis, which can fail or be slow for raw pointers
+//! or `#[repr(C)]` structs. With the `type_tree` option, `differentiate_ext` emits the type tree
+//! of each parameter in a static next to the declaration, so the backend doesn't have to guess.
+//! Trees are built while compiling, since the static has to be known to the backend.
+//!
+//! The text format is the one of Enzyme, e.g. `{[0]:Pointer, [0,-1]:Float@double}`
+//! for a pointer to doubles. Each path lists the byte offsets from the outermost value inwards,
+//! `-1` stands for every offset.
+//!
+//! Trees are fixed size arrays, since they are built in `const` context. A tree holds at most
+//! 128 scalars. Arrays of floats of one kind or of integers count once, also as struct fields,
+//! so `[f64; 1000]` is fine, while an array of 100 structs mixing floats and integers counts each
+//! of their scalars. Paths are at most three offsets deep, so pointers can be nested twice.
+//! The text lists every element of arrays inside structs, since Enzyme has no notation for ranges,
+//! and is limited to 8 KiB for all trees of one function, e.g. a struct holding a few hundred floats.
+//! Larger types fail to compile with an error naming the limit;
+//! pass them behind a pointer to a uniform array or leave out the `type_tree` option for them.
+
+/// The type of a scalar in a type tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Float,
+    Double,
+    Integer,
+    Pointer,
+}
+
+impl Kind {
+    const fn text(self) -> &'static [u8] {
+        match self {
+            Kind::Float => b"Float@float",
+            Kind::Double => b"Float@double",
+            Kind::Integer => b"Integer",
+            Kind::Pointer => b"Pointer",
+        }
+    }
+}
+
+/// The maximal number of offsets in a path, so pointers can be nested twice.
+const DEPTH: usize = 3;
+/// The maximal number of scalars in a tree, larger arrays of floats or integers only count once.
+const ENTRIES: usize = 128;
+
+/// `count` scalars of one kind, the first at `path`, the others each `stride` bytes further
+/// at the last offset of the path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Entry {
+    path: [isize; DEPTH],
+    depth: usize,
+    kind: Kind,
+    count: usize,
+    stride: usize,
+}
+
+const EMPTY: Entry = Entry {
+    path: [0; DEPTH],
+    depth: 0,
+    kind: Kind::Integer,
+    count: 1,
+    stride: 0,
+};
+
+/// The scalars of a type, see the module documentation.
+#[derive(Debug, Clone, Copy)]
+pub struct TypeTree {
+    /// Set if all bytes of the value belong to scalars of this kind, e.g. for `[f64; 1000]`.
+    uniform: Option<Kind>,
+    size: usize,
+    entries: [Entry; ENTRIES],
+    len: usize,
+}
+
+impl TypeTree {
+    /// A tree without any scalars, e.g. for padding only types.
+    pub const fn empty(size: usize) -> Self {
+        TypeTree {
+            uniform: None,
+            size,
+            entries: [EMPTY; ENTRIES],
+            len: 0,
+        }
+    }
+
+    /// A value of `size` bytes, which consists of scalars of one kind only.
+    pub const fn scalar(kind: Kind, size: usize) -> Self {
+        TypeTree {
+            uniform: Some(kind),
+            ..TypeTree::empty(size)
+        }
+    }
+
+    /// A pointer to a value described by `pointee`.
+    pub const fn pointer(pointee: &TypeTree) -> Self {
+        let mut tree = TypeTree::empty(std::mem::size_of::<*const u8>());
+        tree = tree.push([0, 0, 0], 1, Kind::Pointer, 1, 0);
+        if let Some(kind) = pointee.uniform {
+            return tree.push([0, -1, 0], 2, kind, 1, 0);
+        }
+        let mut i = 0;
+        while i < pointee.len {
+            let entry = pointee.entries[i];
+            if entry.depth == DEPTH {
+                panic!("Type trees only support two levels of pointers, see `autodiff_runtime::type_tree`!");
+            }
+            let mut path = [0; DEPTH];
+            let mut j = 0;
+            while j < entry.depth {
+                path[j + 1] = entry.path[j];
+                j += 1;
+            }
+            tree = tree.push(path, entry.depth + 1, entry.kind, entry.count, entry.stride);
+            i += 1;
+        }
+        tree
+    }
+
+    /// `n` values described by `elem`, each `stride` bytes after the previous one.
+    pub const fn array(elem: &TypeTree, n: usize, stride: usize) -> Self {
+        if let Some(kind) = elem.uniform {
+            if stride == elem.size {
+                return TypeTree::scalar(kind, n * stride);
+            }
+        }
+        let mut tree = TypeTree::empty(n * stride);
+        let mut i = 0;
+        while i < n {
+            tree = tree.field(i * stride, elem);
+            i += 1;
+        }
+        tree
+    }
+
+    /// Adds the scalars of a field at `offset`, for building the trees of structs.
+    pub const fn field(self, offset: usize, field: &TypeTree) -> Self {
+        let mut tree = self;
+        if let Some(kind) = field.uniform {
+            let scalar = match kind {
+                Kind::Float => 4,
+                Kind::Double => 8,
+                Kind::Integer => 1,
+                Kind::Pointer => std::mem::size_of::<*const u8>(),
+            };
+            if field.size == 0 {
+                return tree;
+            }
+            // Integers of all sizes are just `Integer`, so one entry at the start of the field is
+            // enough. Floats are one range entry, which the text lists element-wise.
+            return match kind {
+                Kind::Integer => tree.push([offset as isize, 0, 0], 1, kind, 1, 0),
+                _ => tree.push(
+                    [offset as isize, 0, 0],
+                    1,
+                    kind,
+                    field.size / scalar,
+                    scalar,
+                ),
+            };
+        }
+        let mut i = 0;
+        while i < field.len {
+            let mut entry = field.entries[i];
+            entry.path[0] += offset as isize;
+            tree = tree.push(
+                entry.path,
+                entry.depth,
+                entry.kind,
+                entry.count,
+                entry.stride,
+            );
+            i += 1;
+        }
+        tree
+    }
+
+    const fn push(
+        mut self,
+        path: [isize; DEPTH],
+        depth: usize,
+        kind: Kind,
+        count: usize,
+        stride: usize,
+    ) -> Self {
+        if self.len == ENTRIES {
+            panic!("This type has more than 128 scalars, which is the limit of type trees! Arrays of one kind of scalar count once, see `autodiff_runtime::type_tree`.");
+        }
+        self.entries[self.len] = Entry {
+            path,
+            depth,
+            kind,
+            count,
+            stride,
+        };
+        self.len += 1;
+        self
+    }
+}
+
+/// Types whose type tree is known, usually through `#[derive(HasTypeTree)]` for structs.
+///
+/// Using `TREE` fails to compile for types beyond the limits of type trees, see the module documentation.
+///
+/// # Safety
+///
+/// `TREE` must describe the layout of `Self` exactly, the backend relies on it.
+pub unsafe trait HasTypeTree {
+    const TREE: TypeTree;
+}
+
+macro_rules! scalars {
+    ($kind:ident: $($ty:ty),*) => {$(
+        unsafe impl HasTypeTree for $ty {
+            const TREE: TypeTree = TypeTree::scalar(Kind::$kind, std::mem::size_of::<$ty>());
+        }
+    )*};
+}
+scalars!(Float: f32);
+scalars!(Double: f64);
+scalars!(Integer: i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize, bool, char);
+
+unsafe impl<T: HasTypeTree, const N: usize> HasTypeTree for [T; N] {
+    const TREE: TypeTree = TypeTree::array(&T::TREE, N, std::mem::size_of::<T>());
+}
+
+macro_rules! pointers {
+    ($($ty:ty),*) => {$(
+        unsafe impl<T: HasTypeTree> HasTypeTree for $ty {
+            const TREE: TypeTree = TypeTree::pointer(&T::TREE);
+        }
+    )*};
+}
+pointers!(&T, &mut T, *const T, *mut T);
+
+/// The text of the type trees of all parameters of a function, built while compiling.
+///
+/// It holds one line `<i>=<tree>` per parameter and `ret=<tree>` for the return value.
+#[derive(Debug, Clone, Copy)]
+pub struct TypeTreeText {
+    bytes: [u8; 8192],
+    len: usize,
+    params: usize,
+}
+
+impl TypeTreeText {
+    pub const fn new() -> Self {
+        TypeTreeText {
+            bytes: [0; 8192],
+            len: 0,
+            params: 0,
+        }
+    }
+
+    /// Adds the tree of the next parameter.
+    pub const fn param(mut self, tree: &TypeTree) -> Self {
+        self = self.number(self.params as isize).text(b"=").tree(tree);
+        self.params += 1;
+        self
+    }
+
+    /// Adds the tree of the return value.
+    pub const fn ret(self, tree: &TypeTree) -> Self {
+        self.text(b"ret=").tree(tree)
+    }
+
+    /// The length of the text, including the terminating nul.
+    pub const fn len(&self) -> usize {
+        self.len + 1
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The nul-terminated text, `N` has to be `self.len()`.
+    pub const fn bytes<const N: usize>(&self) -> [u8; N] {
+        let mut out = [0; N];
+        let mut i = 0;
+        while i < self.len {
+            out[i] = self.bytes[i];
+            i += 1;
+        }
+        out
+    }
+
+    const fn tree(mut self, tree: &TypeTree) -> Self {
+        self = self.text(b"{");
+        if let Some(kind) = tree.uniform {
+            self = self.text(b"[-1]:").text(kind.text());
+        }
+        let mut first = true;
+        let mut i = 0;
+        while i < tree.len {
+            let entry = tree.entries[i];
+            let mut k = 0;
+            while k < entry.count {
+                if !first {
+                    self = self.text(b", ");
+                }
+                first = false;
+                self = self.text(b"[");
+                let mut j = 0;
+                while j < entry.depth {
+                    if j > 0 {
+                        self = self.text(b",");
+                    }
+                    let mut at = entry.path[j];
+                    if j + 1 == entry.depth {
+                        at += (k * entry.stride) as isize;
+                    }
+                    self = self.number(at);
+                    j += 1;
+                }
+                self = self.text(b"]:").text(entry.kind.text());
+                k += 1;
+            }
+            i += 1;
+        }
+        self.text(b"}\n")
+    }
+
+    const fn number(mut self, n: isize) -> Self {
+        if n < 0 {
+            self = self.text(b"-");
+        }
+        let mut digits = [0u8; 20];
+        let mut len = 0;
+        let mut n = n.unsigned_abs();
+        loop {
+            digits[len] = b'0' + (n % 10) as u8;
+            len += 1;
+            n /= 10;
+            if n == 0 {
+                break;
+            }
+        }
+        while len > 0 {
+            len -= 1;
+            self = self.text(&[digits[len]]);
+        }
+        self
+    }
+
+    const fn text(mut self, text: &[u8]) -> Self {
+        let mut i = 0;
+        while i < text.len() {
+            if self.len == self.bytes.len() - 1 {
+                panic!("The type trees of this function are larger than 8 KiB, which is the limit of type trees, see `autodiff_runtime::type_tree`!");
+            }
+            self.bytes[self.len] = text[i];
+            self.len += 1;
+            i += 1;
+        }
+        self
+    }
+}
+
+impl Default for TypeTreeText {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,86 @@
+use autodiff_runtime::{Differentiable, Tangent};
+
+#[test]
+fn floats() {
+    let mut x = 1.0f64;
+    x.axpy(2.0, &3.0);
+    assert_eq!(x, 7.0);
+    assert_eq!(x.flatten(), [7.0]);
+    assert_eq!(f32::unflatten(&[0.5]), 0.5f32);
+}
+
+#[test]
+fn arrays() {
+    type Grid = <[[f64; 2]; 3] as Differentiable>::Tangent;
+    assert_eq!(Grid::LEN, 6);
+    assert_eq!(Grid::zero(), [[0.0; 2]; 3]);
+    let grid: Grid = [[1.0, 2.0], [3.0, 4.0], [5.0, 6.0]];
+    let flat = grid.flatten();
+    assert_eq!(flat, [1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+    assert_eq!(Grid::unflatten(&flat), grid);
+    let mut acc = Grid::zero();
+    acc.axpy(0.5, &grid);
+    assert_eq!(acc[2], [2.5, 3.0]);
+}
+
+#[test]
+fn passive() {
+    assert_eq!(<usize as Tangent>::LEN, 0);
+    assert_eq!(<[u8; 4] as Tangent>::LEN, 0);
+    let mut n = 3usize;
+    n.axpy(2.0, &5);
+    assert_eq!(n, 3);
+}
+
+#[test]
+fn buffers() {
+    use autodiff_runtime::AdBuffer;
+    let mut x = vec![1.0, 2.0, 3.0];
+    assert_eq!(x.zeroed_shadow(), [0.0; 3]);
+    let (ptr, len) = x.as_raw_mut();
+    let view = unsafe { Vec::view(ptr, len) };
+    assert_eq!(**view, [1.0, 2.0, 3.0]);
+
+    let mut b: Box<[f32]> = Box::new([4.0, 5.0]);
+    let (ptr, len) = b.as_raw_mut();
+    let view = unsafe { <Box<[f32]>>::view(ptr, len) };
+    assert_eq!(view[1], 5.0);
+    assert_eq!(b.zeroed_shadow().len(), 2);
+}
+
+#[test]
+fn revolve() {
+    use std::cell::Cell;
+    for n in [1, 2, 7, 100] {
+        for snapshots in [0, 1, 3, 10] {
+            let steps = Cell::new(0);
+            let mut reversed = vec![];
+            autodiff_runtime::revolve(
+                &0usize,
+                n,
+                snapshots,
+                |i| {
+                    *i += 1;
+                    steps.set(steps.get() + 1);
+                },
+                |i| reversed.push(*i),
+            );
+            // Each adjoint sees the state before its step, last step first.
+            assert_eq!(reversed, (0..n).rev().collect::<Vec<_>>());
+            if snapshots >= n {
+                // Enough snapshots to store every state, so each step runs at most once.
+                assert!(steps.get() < n, "{n} steps, {snapshots} snapshots");
+            }
+        }
+    }
+}
+
+#[test]
+fn stop_gradient() {
+    use autodiff_runtime::{stop_gradient, StopGradient};
+    assert_eq!(stop_gradient(2.5f64), 2.5);
+    assert_eq!(1.5f32.inactive(), 1.5);
+    assert_eq!([1.0f64, 2.0].inactive(), [1.0, 2.0]);
+    assert_eq!(stop_gradient(3_usize), 3);
+    assert_eq!(stop_gradient((1.0f64, true, [2.0f32])), (1.0, true, [2.0]));
+}
@@ -0,0 +1,69 @@
+use autodiff_runtime::type_tree::{TypeTree, TypeTreeText};
+use autodiff_runtime::HasTypeTree;
+
+#[repr(C)]
+struct Particle {
+    pos: [f32; 2],
+    id: u64,
+    mass: f64,
+}
+unsafe impl HasTypeTree for Particle {
+    const TREE: TypeTree = TypeTree::empty(std::mem::size_of::<Self>())
+        .field(std::mem::offset_of!(Self, pos), &<[f32; 2]>::TREE)
+        .field(std::mem::offset_of!(Self, id), &u64::TREE)
+        .field(std::mem::offset_of!(Self, mass), &f64::TREE);
+}
+
+#[test]
+fn text() {
+    const TEXT: TypeTreeText = TypeTreeText::new()
+        .param(&<*const [f64; 1000]>::TREE)
+        .param(&<&[Particle; 2]>::TREE)
+        .param(&usize::TREE)
+        .ret(&f32::TREE);
+    static BYTES: [u8; TEXT.len()] = TEXT.bytes();
+    let text = std::ffi::CStr::from_bytes_with_nul(&BYTES).unwrap();
+    assert_eq!(
+        text.to_str().unwrap(),
+        "0={[0]:Pointer, [0,-1]:Float@double}\n\
+         1={[0]:Pointer, [0,0]:Float@float, [0,4]:Float@float, [0,8]:Integer, [0,16]:Float@double, \
+         [0,24]:Float@float, [0,28]:Float@float, [0,32]:Integer, [0,40]:Float@double}\n\
+         2={[-1]:Integer}\n\
+         ret={[-1]:Float@float}\n"
+    );
+}
+
+#[test]
+#[should_panic(expected = "more than 128 scalars")]
+fn too_many_scalars() {
+    // 3 entries per particle, which don't share one kind, so the array can't be collapsed.
+    TypeTree::array(&Particle::TREE, 43, std::mem::size_of::<Particle>());
+}
+
+#[test]
+fn uniform_array_field() {
+    #[repr(C)]
+    struct Big {
+        data: [f64; 200],
+        n: u32,
+    }
+    unsafe impl HasTypeTree for Big {
+        const TREE: TypeTree = TypeTree::empty(std::mem::size_of::<Self>())
+            .field(std::mem::offset_of!(Self, data), &<[f64; 200]>::TREE)
+            .field(std::mem::offset_of!(Self, n), &u32::TREE);
+    }
+
+    const TEXT: TypeTreeText = TypeTreeText::new().param(&<&Big>::TREE);
+    static BYTES: [u8; TEXT.len()] = TEXT.bytes();
+    let text = std::ffi::CStr::from_bytes_with_nul(&BYTES).unwrap();
+    let floats: Vec<String> = (0..200)
+        .map(|i| format!("[0,{}]:Float@double", 8 * i))
+        .collect();
+    assert_eq!(
+        text.to_str().unwrap(),
+        format!(
+            "0={{[0]:Pointer, {}, [0,1600]:Integer}}\n",
+            floats.join(", ")
+        )
+    );
+}
@@ -3,99 +3,221 @@
 //! The core is our differentiate attribute-proc-macro.  
 //! The parameters which it accepts might can differ slightly depending on the mode which you select.  
 //! This is how it will generaly look like.  
-//! `#[differentiate(grad_fnc_name, mode, activity_inputs, activity_output, parallel_context)]`
+//! `#[differentiate(grad_fnc_name, mode, activity_inputs, activity_output, parallel_context)]`  
+//! `parallel_context` only applies to reverse mode and can be omitted, it defaults to `false`.  
+//! Optional `key = value` settings can be appended, see `autodiff_codegen::Options`.
+//!
+//! The macros are thin wrappers around the `autodiff-codegen` crate,
+//! which can also be used directly from build scripts or other tools.
 
 #![allow(unused_macros)]
 #![doc(html_logo_url = "https://enzyme.mit.edu//logo.svg")]
 
-use modes::{forward, reverse};
+use autodiff_codegen::DiffConfig;
 use proc_macro::TokenStream;
-use proc_macro2::TokenStream as TS2;
 use quote::*;
-use syn::punctuated::Punctuated;
-use syn::token;
 use syn::*;
 
-mod types;
-use types::DiffMode;
-#[doc(hidden)]
-mod helper;
-mod modes;
-
-#[doc(hidden)]
-// only append no_mangle if not already in there
-fn append_no_mangle(item: &mut ItemFn) {
-    let nomangle_attr = Attribute {
-        pound_token: Default::default(),
-        style: AttrStyle::Outer,
-        bracket_token: Default::default(),
-        path: Path {
-            leading_colon: None,
-            segments: Punctuated::new(),
-        },
-        tokens: quote! { no_mangle },
-    };
-
-    // don't add it multiple times
-    for attr in item.attrs.iter() {
-        let attr_ps = &attr.path.segments;
-        if attr_ps.len() == 1 {
-            // no_mangle attr has one segment
-            let id = attr_ps[0].ident.to_string();
-            if id == *"no_mangle".to_owned() {
-                return;
-            }
-        }
-    }
-    item.attrs.push(nomangle_attr);
-}
-
 /// Thisis a preview for a generic differentiate macro, adjusted for oxide-enzyme.  
 ///
+/// The name of the derivative can be omitted, it's then derived from a naming template
+/// (`d_<name>` by default).  
+///
 /// It will generate and wrap the extern "C" section users had to write previously.  
 /// It is still based on the C-ABI, so all the related issues still apply,
-/// but at least it's nicer to use.
+/// but at least it's nicer to use.  
+/// The primal function itself is left untouched, Enzyme will instead differentiate an
+/// `extern "C"` shim named `__enzyme_primal_<name>` which calls it.
+///
+/// It can also be placed on functions in `extern "C"` blocks, e.g. C functions which the backend
+/// gets as bitcode. Then only the declaration of the derivative is generated, next to the primal.
+/// Placed on an extern block with a single function instead, it also emits the return struct
+/// and metadata, like for functions with a body.
 #[proc_macro_attribute]
 pub fn differentiate_ext(attr: TokenStream, item: TokenStream) -> TokenStream {
-    let input: DiffMode = parse_macro_input!(attr as DiffMode);
-    let mut primary_fnc: ItemFn = parse_macro_input!(item as ItemFn);
-    append_no_mangle(&mut primary_fnc);
-    let mut fnc = ForeignItemFn {
-        semi_token: token::Semi::default(),
-        attrs: vec![],
-        vis: primary_fnc.vis.clone(),
-        sig: primary_fnc.sig.clone(),
+    let input: DiffConfig = parse_macro_input!(attr as DiffConfig);
+    let primary_fnc: ItemFn = match syn::parse(item.clone()) {
+        Ok(primary_fnc) => primary_fnc,
+        Err(e) => {
+            if let Ok(foreign) = syn::parse::<ForeignItemFn>(item.clone()) {
+                return differentiate_foreign(input, foreign);
+            }
+            return match syn::parse::<ItemForeignMod>(item) {
+                Ok(block) => differentiate_extern_block(input, block),
+                Err(_) => e.to_compile_error().into(),
+            };
+        }
     };
+    let explain = input.options().explain;
+    match autodiff_codegen::generate(input, &primary_fnc) {
+        Ok(generated) => {
+            let mut out = generated.to_token_stream();
+            if explain {
+                out.extend(explain_warning(&generated));
+            }
+            out.into()
+        }
+        Err(e) => {
+            // Keep the primal around, to not cause follow-up errors at its call sites.
+            let mut out = primary_fnc.to_token_stream();
+            out.extend(e.to_compile_error());
+            out.into()
+        }
+    }
+}
+
+/// Shows the explanation of a derivative as warning at its name.
+///
+/// Stable proc-macros can't emit diagnostics of their own, so we use a deprecated constant,
+/// whose note rustc reports where we use it.
+fn explain_warning(generated: &autodiff_codegen::Generated) -> proc_macro2::TokenStream {
+    let name = &generated.declaration.sig.ident;
+    let note = format!("\n{}", generated.explanation());
+    let ident = Ident::new(&format!("__enzyme_explain_{name}"), name.span());
+    let usage = quote_spanned! { name.span() => #ident };
+    quote! {
+        const _: () = {
+            #[deprecated(note = #note)]
+            #[allow(non_upper_case_globals)]
+            const #ident: () = ();
+            #usage
+        };
+    }
+}
+
+// Attributes in extern blocks can only expand to foreign items, so we emit the declaration only.
+fn differentiate_foreign(input: DiffConfig, primary_fnc: ForeignItemFn) -> TokenStream {
     let mut out = primary_fnc.to_token_stream();
-    adjust_name(input.name(), &mut fnc);
-    let ret_struct_def: Option<syn::ItemStruct> = adjust_parameters(input, &mut fnc);
-    let ext_block: TS2 = quote! {
-        extern "C" { #fnc }
-    };
+    match autodiff_codegen::generate_foreign(input, &primary_fnc) {
+        Ok(declaration) => declaration.to_tokens(&mut out),
+        Err(e) => out.extend(e.to_compile_error()),
+    }
+    out.into()
+}
 
-    out.extend(ext_block);
-    if let Some(struct_def) = ret_struct_def {
-        out.extend(struct_def.to_token_stream());
+// Outside of the block we can emit everything besides a shim, which foreign functions don't need.
+fn differentiate_extern_block(input: DiffConfig, block: ItemForeignMod) -> TokenStream {
+    let mut out = block.to_token_stream();
+    match autodiff_codegen::generate_extern_block(input, &block) {
+        Ok(generated) => out.extend(generated),
+        Err(e) => out.extend(e.to_compile_error()),
     }
     out.into()
 }
 
-#[doc(hidden)]
-fn adjust_name(new_name: syn::Ident, fnc: &mut ForeignItemFn) {
-    assert_ne!(
-        fnc.sig.ident, new_name,
-        "Please give the gradient function to be generated a new name!"
-    );
-    fnc.sig.ident = new_name;
+/// Declares derivatives of functions defined elsewhere, e.g. in other crates or vendored code,
+/// which can't get a `differentiate_ext` attribute.
+///
+/// `declare_derivative!(d_f = other_crate::f(x: f64, y: &[f64]) -> f64, Reverse, PerInput(Active, Duplicated), Active)`
+/// generates the same declaration and return struct as `differentiate_ext` on `f` would,
+/// after the `=` the signature of `f` has to be repeated, with its path.
+/// Multiple derivatives can be separated by `;`, the ones of the same function share one shim,
+/// so all of them have to be declared in one invocation.
+#[proc_macro]
+pub fn declare_derivative(input: TokenStream) -> TokenStream {
+    let declarations = parse_macro_input!(input as autodiff_codegen::declare::Declarations);
+    match autodiff_codegen::declare::declare(&declarations) {
+        Ok(out) => out.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+/// Derives `autodiff_runtime::Differentiable` for a `#[repr(C)]` struct of parameters.
+///
+/// It generates a `<Name>Tangent` struct with the same layout, which can be used as shadow,
+/// and implements `autodiff_runtime::Tangent` for it: `zero()`, `axpy`,
+/// `flatten_into(&mut [f64])` and `unflatten`, e.g. to pass the gradient to an optimizer.
+/// All fields need to be `Differentiable` themselves, which is the case for floats,
+/// integers, arrays of them and other structs deriving it.
+/// List such structs in the `tangent` option of `differentiate_ext` to type the shadows of
+/// references and pointers to them as tangents.
+#[proc_macro_derive(Differentiable)]
+pub fn derive_differentiable(item: TokenStream) -> TokenStream {
+    let input: DeriveInput = parse_macro_input!(item as DeriveInput);
+    match autodiff_codegen::differentiable::derive(&input) {
+        Ok(out) => out.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+/// Derives `autodiff_runtime::HasTypeTree` for a `#[repr(C)]` struct,
+/// so it can be used with the `type_tree` option of `differentiate_ext`.
+///
+/// The tree lists the floats, integers and pointers of all fields at their offsets,
+/// so all fields need to implement `HasTypeTree` themselves, which is the case for floats,
+/// integers, arrays of them, references and pointers to them and other structs deriving it.
+#[proc_macro_derive(HasTypeTree)]
+pub fn derive_has_type_tree(item: TokenStream) -> TokenStream {
+    let input: DeriveInput = parse_macro_input!(item as DeriveInput);
+    match autodiff_codegen::type_tree::derive(&input) {
+        Ok(out) => out.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+/// Marks a helper function for checkpointing:
+/// the derivatives of functions calling it recompute its calls in the reverse pass,
+/// instead of caching the intermediate values computed by them. This trades memory for time.
+///
+/// The function is exported under its own name (plus the `AUTODIFF_SYMBOL_PREFIX`)
+/// and never inlined, so the backend can find its calls.
+/// To only checkpoint a helper in some derivatives,
+/// use the `checkpoint = [helper]` option of `differentiate_ext` instead.
+#[proc_macro_attribute]
+pub fn checkpoint(attr: TokenStream, item: TokenStream) -> TokenStream {
+    annotation(attr, item, autodiff_codegen::annotations::checkpoint)
 }
 
-#[doc(hidden)]
-pub(crate) fn adjust_parameters(
-    input: DiffMode,
-    fnc: &mut ForeignItemFn,
-) -> Option<syn::ItemStruct> {
-    match input {
-        DiffMode::Fwd(f) => forward::adjust_parameters(f, fnc),
-        DiffMode::Rev(r) => reverse::adjust_parameters(r, fnc),
+/// Marks functions and statics as inactive, e.g. logging, random number generators or counters.
+/// The backend then treats them as constant, so they never contribute to a derivative.
+///
+/// They are exported under their own name (plus the `AUTODIFF_SYMBOL_PREFIX`),
+/// and functions are never inlined, so the backend can find them.
+#[proc_macro_attribute]
+pub fn inactive(attr: TokenStream, item: TokenStream) -> TokenStream {
+    annotation(attr, item, autodiff_codegen::annotations::inactive)
+}
+
+fn annotation<T: parse::Parse + ToTokens>(
+    attr: TokenStream,
+    item: TokenStream,
+    annotate: fn(&T) -> Result<proc_macro2::TokenStream>,
+) -> TokenStream {
+    let item: T = parse_macro_input!(item as T);
+    if !attr.is_empty() {
+        let attr = proc_macro2::TokenStream::from(attr);
+        let mut out = item.to_token_stream();
+        out.extend(
+            Error::new_spanned(attr, "This attribute takes no arguments!").to_compile_error(),
+        );
+        return out.into();
+    }
+    match annotate(&item) {
+        Ok(out) => out.into(),
+        Err(e) => {
+            let mut out = item.to_token_stream();
+            out.extend(e.to_compile_error());
+            out.into()
+        }
     }
 }
+
+/// Generates `<name>_trajectory_adjoint` for a step function like `fn step(state: &mut State, dt: f64)`,
+/// which runs the reverse pass through `n_steps` applications of it,
+/// storing at most `snapshots` intermediate states (binomial checkpointing).
+///
+/// The argument is the name of a reverse mode derivative of the step function,
+/// declared by a `differentiate_ext` attribute below this one,
+/// with the state as only `Duplicated` parameter.
+/// `State` has to implement `Clone` and the crate has to depend on `autodiff-runtime`.
+#[proc_macro_attribute]
+pub fn trajectory(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let derivative: Ident = parse_macro_input!(attr as Ident);
+    let primal: ItemFn = parse_macro_input!(item as ItemFn);
+    let mut out = primal.to_token_stream();
+    match autodiff_codegen::trajectory::trajectory(&derivative, &primal) {
+        Ok(driver) => driver.to_tokens(&mut out),
+        Err(e) => out.extend(e.to_compile_error()),
+    }
+    out.into()
+}
@@ -0,0 +1,19 @@
+#![allow(unused)]
+#![allow(non_camel_case_types)]
+
+use autodiff::differentiate_ext;
+
+// Infers PerInput(Active, Duplicated, Constant)
+#[differentiate_ext(d_rev_h, Reverse, Auto, Active, false)]
+// Infers PerInput(Duplicated, Duplicated, Constant)
+#[differentiate_ext(d_fwd_h, Forward, Auto, Gradient)]
+// Overrides the inference for x
+#[differentiate_ext(d_rev_h1, Reverse, Auto(x = Constant), Gradient, false)]
+// Overrides the inference for y
+#[differentiate_ext(d_fwd_h1, Forward(2), Auto(y = Constant), Active)]
+fn h(x: f64, y: &[f64; 2], n: usize) -> f64 {
+    x * y[0] * y[1] * n as f64
+}
+
+#[differentiate_ext(d_rev_k, Reverse, Auto(scale = Constant), None, false)]
+fn k(data: *mut f32, len: u32, scale: f32) {}
@@ -0,0 +1,81 @@
+#![allow(unused)]
+#![allow(clippy::ptr_arg)]
+
+use autodiff::differentiate_ext;
+use autodiff_runtime::AdBuffer;
+use std::mem::ManuallyDrop;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// Generates:
+// extern "C" {
+//   fn d_sum(x: *const f64, x: *mut f64, x_len: usize, scale: f64) -> f64;
+// }
+// unsafe fn d_sum(x: &Vec<f64>, d_x: &mut Vec<f64>, scale: f64) -> f64;
+#[differentiate_ext(d_sum, Reverse, PerInput(Duplicated, Constant), Constant, false)]
+fn sum(x: &Vec<f64>, scale: f64) -> f64 {
+    x.iter().sum::<f64>() * scale
+}
+
+#[differentiate_ext(d_scale_fwd, Forward(2), Auto, Gradient)]
+#[differentiate_ext(d_scale, Reverse, Auto, None, false)]
+fn scale(x: &[f64], a: f64) -> f64 {
+    x.iter().map(|x| x * a).sum()
+}
+
+// Containers are only passed by shared reference, elements are modified through slices.
+#[differentiate_ext(d_shift, Reverse, PerInput(Duplicated, Duplicated), None, false)]
+fn shift(x: &Vec<f64>, out: &mut [f64]) {
+    out.iter_mut().zip(x).for_each(|(out, x)| *out += x);
+}
+
+/// A row-major matrix, which only passes its elements to the derivative.
+pub struct Matrix {
+    data: Vec<f64>,
+}
+
+unsafe impl AdBuffer for Matrix {
+    type Elem = f64;
+    fn as_raw(&self) -> (*const f64, usize) {
+        self.data.as_raw()
+    }
+    fn as_raw_mut(&mut self) -> (*mut f64, usize) {
+        self.data.as_raw_mut()
+    }
+    fn zeroed_shadow(&self) -> Self {
+        Matrix {
+            data: self.data.zeroed_shadow(),
+        }
+    }
+    unsafe fn view(ptr: *mut f64, len: usize) -> ManuallyDrop<Self> {
+        ManuallyDrop::new(Matrix {
+            data: ManuallyDrop::into_inner(Vec::view(ptr, len)),
+        })
+    }
+}
+
+#[differentiate_ext(d_trace, Reverse, PerInput(Duplicated), Active, false, buffer = [Matrix])]
+fn trace(m: &Matrix) -> f64 {
+    m.data.iter().step_by(3).sum()
+}
+
+fn use_buffers(x: &Vec<f64>, m: &Matrix) -> f64 {
+    let mut d_x = x.zeroed_shadow();
+    let mut d_m = m.zeroed_shadow();
+    let _trace = unsafe { d_trace(m, &mut d_m) };
+    unsafe { d_sum(x, &mut d_x, 2.0) }
+}
+
+// In a parallel context the shadow of a slice holds atomics:
+// unsafe fn d_par_sum(x: &[f64], d_x: &[AtomicU64]) -> f64
+#[differentiate_ext(d_par_sum, Reverse, PerInput(Duplicated), Active, true)]
+pub fn par_sum(x: &[f64]) -> f64 {
+    x.iter().sum()
+}
+
+fn use_parallel(x: &[f64]) -> Vec<f64> {
+    let d_x: Vec<AtomicU64> = x.iter().map(|_| AtomicU64::new(0)).collect();
+    let _sum = unsafe { d_par_sum(x, &d_x) };
+    d_x.iter()
+        .map(|d| f64::from_bits(d.load(Ordering::Relaxed)))
+        .collect()
+}
@@ -0,0 +1,47 @@
+#![allow(unused)]
+
+use autodiff::differentiate_ext;
+
+// The right-hand side of an ODE, as integrators take it.
+#[differentiate_ext(
+    d_rhs,
+    Reverse,
+    PerInput(Constant, Duplicated, Duplicated),
+    None,
+    false
+)]
+extern "C" fn rhs(t: f64, y: *const f64, dy: *mut f64) {
+    unsafe { *dy = -t * *y };
+}
+
+// Generates:
+// extern "C" {
+//   fn d_euler(
+//     rhs: extern "C" fn(f64, *const f64, *mut f64),
+//     rhs: __enzyme_fn_d_rhs,
+//     y: &mut f64, y: &mut f64, dt: f64,
+//   );
+// }
+#[differentiate_ext(d_euler, Reverse, PerInput(Duplicated, Duplicated, Constant), None, false, callback(rhs = d_rhs))]
+fn euler(rhs: extern "C" fn(f64, *const f64, *mut f64), y: &mut f64, dt: f64) {
+    let mut dy = 0.0;
+    rhs(0.0, y, &mut dy);
+    *y += dt * dy;
+}
+
+// Forward mode shadows get the forward derivative of the callee, once per direction.
+#[differentiate_ext(d_decay, Forward(2), PerInput(Constant, Duplicated), Active)]
+extern "C" fn decay(t: f64, y: *const f64) -> f64 {
+    unsafe { -t * *y }
+}
+
+#[differentiate_ext(d_step, Forward(2), PerInput(Duplicated, Duplicated, Constant), Active, callback(f = d_decay))]
+fn step(f: extern "C" fn(f64, *const f64) -> f64, y: &f64, dt: f64) -> f64 {
+    y + dt * f(0.0, y)
+}
+
+fn adjoint(y: f64) -> f64 {
+    let (mut y, mut d_y) = (y, 1.0);
+    unsafe { d_euler(rhs, d_rhs, &mut y, &mut d_y, 0.1) };
+    d_y
+}
@@ -0,0 +1,22 @@
+#![allow(unused)]
+
+use autodiff::{checkpoint, differentiate_ext};
+
+// Exported as `step` and never inlined, so the backend recomputes its calls in the reverse pass.
+#[checkpoint]
+fn step(x: f64, dt: f64) -> f64 {
+    x + dt * x.sin()
+}
+
+fn smooth(x: f64) -> f64 {
+    x.tanh()
+}
+
+#[differentiate_ext(d_simulate, Reverse, All(Active), Active, false, checkpoint = [smooth])]
+fn simulate(x0: f64, dt: f64) -> f64 {
+    let mut x = x0;
+    for _ in 0..1000 {
+        x = smooth(step(x, dt));
+    }
+    x
+}
@@ -0,0 +1,24 @@
+#![allow(unused)]
+
+use autodiff::declare_derivative;
+
+mod physics {
+    pub fn energy(x: f64, v: &[f64; 3]) -> f64 {
+        x * v.iter().map(|v| v * v).sum::<f64>()
+    }
+}
+
+// Generates the same as `#[differentiate_ext(d_energy, ...)]` on `physics::energy` would:
+// extern "C" {
+//   pub fn d_energy(x: f64, x: f64, v: &[f64; 3], v: &mut [f64; 3]) -> d_energy_ret;
+// }
+declare_derivative!(
+    pub d_energy = physics::energy(x: f64, v: &[f64; 3]) -> f64, Reverse, PerInput(Active, Duplicated), Active;
+    pub d_energy_fwd = physics::energy(x: f64, v: &[f64; 3]) -> f64, Forward(2), PerInput(Duplicated, Constant), Gradient
+);
+
+declare_derivative!(d_sin = f64::sin(x: f64) -> f64, Reverse, All(Active), None, false);
+
+fn cos(x: f64) -> f64 {
+    unsafe { d_sin(x, 1.0) }
+}
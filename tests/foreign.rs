@@ -0,0 +1,47 @@
+#![allow(unused)]
+
+use autodiff::differentiate_ext;
+
+// C functions which the backend gets as bitcode, e.g. compiled with clang -flto.
+// Generates, in the same block:
+//   fn d_norm2(x: *const f64, x: *mut f64, n: usize) -> f64;
+//   fn d_scale(x: f64, x: f64, s: f64) -> f64;
+//   fn d_scale_s(x: f64, s: f64, s: f64) -> f64;
+extern "C" {
+    #[differentiate_ext(d_norm2, Reverse, PerInput(Duplicated, Constant), Constant, false)]
+    fn norm2(x: *const f64, n: usize) -> f64;
+
+    #[differentiate_ext(d_scale_s, Reverse, PerInput(Constant, Active), None, false)]
+    #[differentiate_ext(d_scale, Reverse, PerInput(Active, Constant), None, false)]
+    fn scale(x: f64, s: f64) -> f64;
+}
+
+fn gradient(x: &[f64]) -> Vec<f64> {
+    let mut d_x = vec![0.0; x.len()];
+    let _norm = unsafe { d_norm2(x.as_ptr(), d_x.as_mut_ptr(), x.len()) };
+    d_x
+}
+
+// On the block, the derivative can return a struct and gets metadata and type trees.
+// Generates, after the block:
+//   extern "C" { fn d_hypot(x: f64, x: f64, y: f64, y: f64) -> HypotGrad; }
+//   static __enzyme_meta_d_hypot: [u8; _] = *b"name=d_hypot\nsymbol=d_hypot\nprimal=hypot\n...";
+//   static __enzyme_typetree_d_hypot: [u8; _] = ...;
+//   struct HypotGrad { primary_ret: f64, primary_grad: f64, x0: f64, x1: f64 }
+#[differentiate_ext(
+    d_hypot,
+    Reverse,
+    All(Active),
+    Active,
+    false,
+    type_tree,
+    ret = "HypotGrad"
+)]
+extern "C" {
+    fn hypot(x: f64, y: f64) -> f64;
+}
+
+fn hypot_gradient(x: f64, y: f64) -> [f64; 2] {
+    let grad: HypotGrad = unsafe { d_hypot(x, 1.0, y, 1.0) };
+    [grad.x0, grad.x1]
+}
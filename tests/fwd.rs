@@ -26,3 +26,44 @@ fn g(x: f32, y: f32) -> ret_tuple {
         b: y * x,
     }
 }
+
+// The tangents are passed as d_x: [f64; 8], d_y: &[f64; 8] and d_z: *const [f64; 8].
+#[differentiate_ext(d_fwd_h, Forward(8), PerInput(Duplicated, Duplicated, Duplicated, Constant), Active, layout = array)]
+fn h(x: f64, y: &f64, z: *const f64, n: usize) -> f64 {
+    x * *y * unsafe { *z } * n as f64
+}
+
+fn directional_derivatives(x: f64, y: &f64, z: *const f64) -> f64 {
+    let d_x = [1.0; 8];
+    let d_y = [0.0; 8];
+    let d_z = [0.0; 8];
+    unsafe { d_fwd_h(x, d_x, y, &d_y, z, &d_z, 3) }
+}
+
+const WIDTH: usize = 4;
+
+// The width comes from a const item, the tangents are passed as arrays like with layout = array.
+// The wrapper returns the struct with primary_grad: [f64; WIDTH], which the algebra scales element-wise.
+#[differentiate_ext(d_fwd_k, Forward(WIDTH), All(Duplicated), Active, algebra)]
+// The width is chosen by the caller:
+// fn d_fwd_k_dyn(x: f64, d_x: *const f64, y: &f64, d_y: *const f64, d_ret: *mut f64, width: usize) -> f64
+#[differentiate_ext(d_fwd_k_dyn, Forward(dyn), All(Duplicated), Active)]
+fn k(x: f64, y: &f64) -> f64 {
+    x * *y
+}
+
+fn runtime_width(x: f64, y: &f64, params: usize) -> f64 {
+    let d_x = vec![1.0; params];
+    let d_y = vec![0.0; params];
+    let mut d_ret = vec![0.0; params];
+    let res = unsafe { d_fwd_k_dyn(x, d_x.as_ptr(), y, d_y.as_ptr(), d_ret.as_mut_ptr(), params) };
+    let res_const = unsafe { d_fwd_k(x, [1.0; WIDTH], y, &[0.0; WIDTH]) };
+    let res_const = res_const.clone() + res_const * 0.5;
+    res + res_const.primary_grad.iter().sum::<f64>() + d_ret.iter().sum::<f64>()
+}
+
+// Arrays by value work in forward mode as well, including their tangents.
+#[differentiate_ext(d_fwd_norm2, Forward(2), All(Duplicated), Active)]
+fn norm2(pos: [f64; 3]) -> f64 {
+    pos.iter().map(|x| x * x).sum()
+}
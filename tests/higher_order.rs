@@ -0,0 +1,28 @@
+#![allow(unused)]
+
+use autodiff::differentiate_ext;
+
+// Generates:
+// extern "C" {
+//   fn d_cube(x: f64, x: f64) -> f64;
+//   fn dd_cube(x: f64, x: f64, d_x: f64) -> f64;
+//   fn ddd_cube(x: f64, x: f64, d_x: f64, d_d_x: f64) -> f64;
+// }
+// where `dd_cube` differentiates `d_cube` with respect to `x`, and `ddd_cube` differentiates `dd_cube`.
+#[differentiate_ext(ddd_cube, Reverse, PerInput(Active, Constant, Constant), None, of = dd_cube)]
+#[differentiate_ext(dd_cube, Reverse, PerInput(Active, Constant), None, of = d_cube)]
+#[differentiate_ext(d_cube, Reverse, All(Active), None)]
+fn cube(x: f64) -> f64 {
+    x * x * x
+}
+
+// Forward over reverse, e.g. for Hessian-vector products.
+#[differentiate_ext(d_grad_fwd, Forward(1), PerInput(Duplicated, Constant, Constant), Gradient, of = d_energy)]
+#[differentiate_ext(d_energy, Reverse, PerInput(Duplicated, Constant), None)]
+fn energy(x: &[f64; 2], k: f64) -> f64 {
+    k * (x[0] * x[0] + x[1] * x[1])
+}
+
+fn second_derivative(x: f64) -> f64 {
+    unsafe { dd_cube(x, 1.0, 1.0) }
+}
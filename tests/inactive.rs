@@ -0,0 +1,25 @@
+#![allow(unused)]
+
+use autodiff::{differentiate_ext, inactive};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Exported as `CALLS`, the backend never differentiates through it.
+#[inactive]
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[inactive]
+fn log_value(x: f64) {
+    CALLS.fetch_add(1, Ordering::Relaxed);
+    eprintln!("x = {x}");
+}
+
+#[inactive]
+pub fn noise(seed: u64) -> f64 {
+    (seed.wrapping_mul(6364136223846793005) >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[differentiate_ext(d_f, Reverse, All(Active), Active, false)]
+fn f(x: f64) -> f64 {
+    log_value(x);
+    x * x + noise(42)
+}
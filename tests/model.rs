@@ -0,0 +1,66 @@
+#![allow(unused)]
+
+use autodiff::{differentiate_ext, Differentiable};
+use autodiff_runtime::Tangent;
+
+#[derive(Differentiable)]
+#[repr(C)]
+pub struct Layer {
+    pub weights: [[f64; 3]; 2],
+    pub bias: [f64; 2],
+}
+
+#[derive(Differentiable)]
+#[repr(C)]
+pub struct Model {
+    pub layers: [Layer; 2],
+    pub scale: f32,
+    pub steps: usize,
+}
+
+#[derive(Differentiable)]
+#[repr(C)]
+struct Pair(f64, f64);
+
+// Generates:
+// extern "C" {
+//   fn d_loss(model: &Model, model: &mut ModelTangent, x: &[f64; 3]) -> f64;
+// }
+#[differentiate_ext(d_loss, Reverse, PerInput(Duplicated, Constant), Constant, false, tangent = [Model])]
+fn loss(model: &Model, x: &[f64; 3]) -> f64 {
+    let mut sum = 0.0;
+    for layer in &model.layers {
+        for (row, bias) in layer.weights.iter().zip(layer.bias) {
+            sum += row.iter().zip(x).map(|(w, x)| w * x).sum::<f64>() + bias;
+        }
+    }
+    sum * model.scale as f64
+}
+
+#[differentiate_ext(d_pair, Forward(1), PerInput(Duplicated), Gradient, tangent = [Pair])]
+fn pair(p: &Pair) -> f64 {
+    p.0 * p.1
+}
+
+#[repr(C)]
+pub struct Point {
+    pub x: f64,
+    pub y: f64,
+}
+
+// Structs which aren't listed keep shadows of their own type, `p: &mut Point`.
+#[differentiate_ext(d_norm, Reverse, PerInput(Duplicated), Active)]
+pub fn norm(p: &Point) -> f64 {
+    (p.x * p.x + p.y * p.y).sqrt()
+}
+
+fn sgd_step(model: &Model, x: &[f64; 3], learning_rate: f64) -> Vec<f64> {
+    let mut grad = ModelTangent::zero();
+    let _loss = unsafe { d_loss(model, &mut grad, x) };
+    let mut params = <Model as autodiff_runtime::Differentiable>::Tangent::zero();
+    params.axpy(-learning_rate, &grad);
+    let mut flat = vec![0.0; ModelTangent::LEN];
+    params.flatten_into(&mut flat);
+    assert_eq!(ModelTangent::unflatten(&flat), params);
+    flat
+}
@@ -44,3 +44,135 @@ fn e(x: &mut f32) {
 fn f(x: &f32, y: f32) -> f32 {
     *x * y
 }
+
+// Shadows are accumulated atomically, since the primal runs on multiple threads.
+#[differentiate_ext(d_g, Reverse, PerInput(Duplicated, Gradient, Active), Active, true)]
+fn g(x: &f64, y: *mut [f32; 4], z: f64) -> f64 {
+    *x * z
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct Pair {
+    a: f64,
+    b: f64,
+}
+
+// Struct arguments and returns are passed the same way to the primal shim and the derivative.
+#[differentiate_ext(d_p, Reverse, PerInput(Active, Constant), Active, false)]
+unsafe fn p(pair: Pair, scale: f64) -> Pair {
+    Pair {
+        a: pair.a * scale,
+        b: pair.b,
+    }
+}
+
+// Exported as my_prefix_d_q and rev_d_q1, so they can't collide with d_q from other modules.
+#[differentiate_ext(d_q, Reverse, All(Active), Active, false, prefix = "my_prefix_")]
+#[differentiate_ext(d_q1, Reverse, All(Active), Gradient, false, prefix = crate)]
+fn q(x: f64) -> f64 {
+    x * x
+}
+
+// Generates d_s, s_grad_rev and s_grad_fwd4, with the return structs d_s_ret, s_grad_rev_ret and
+// s_grad_fwd4_result.
+#[differentiate_ext(Reverse, All(Active), Active, false)]
+#[differentiate_ext(
+    Reverse,
+    All(Active),
+    Active,
+    false,
+    name = "{fn}_grad_{mode}{width}",
+    field = "d_{arg}"
+)]
+#[differentiate_ext(
+    Forward(4),
+    All(Duplicated),
+    Active,
+    name = "{fn}_grad_{mode}{width}",
+    ret = "{name}_result"
+)]
+fn s(x: f64, y: f64) -> f64 {
+    x * y
+}
+
+// d_t_ret is public like t, derives Copy, Default and PartialEq
+// and can be accumulated over mini-batches.
+#[differentiate_ext(
+    d_t,
+    Reverse,
+    All(Active),
+    Active,
+    false,
+    derive = [Copy, Default, PartialEq],
+    algebra
+)]
+#[differentiate_ext(d_t1, Reverse, All(Active), Active, false, vis = "pub(crate)")]
+pub fn t(x: f64, y: f64) -> f64 {
+    x * y
+}
+
+fn accumulate(batch: &[d_t_ret]) -> d_t_ret {
+    let mut sum = d_t_ret::default();
+    for grads in batch {
+        sum += *grads;
+    }
+    sum * (1.0 / batch.len() as f64) + d_t_ret::default()
+}
+
+const DIM: usize = 3;
+
+// Arrays are passed by value through a #[repr(C)] struct at the ABI boundary,
+// d_energy takes and returns plain arrays:
+// fn d_energy(pos: [f64; 3], d_pos: [f64; 3], out: &mut f64, d_out: &mut f64) -> [f64; 3]
+#[differentiate_ext(d_energy, Reverse, PerInput(Active, Duplicated), None, false)]
+fn energy(pos: [f64; 3], out: &mut f64) {
+    *out = pos.iter().map(|x| x * x).sum();
+}
+
+// The gradient of the active array ends up in the array field x0 of d_shift_ret,
+// which is accumulated element-wise by the algebra.
+#[differentiate_ext(
+    d_shift,
+    Reverse,
+    PerInput(Active, Active),
+    Active,
+    false,
+    derive = [Copy],
+    algebra
+)]
+fn shift(pos: [f64; DIM], by: f64) -> [f64; DIM] {
+    pos.map(|x| x + by)
+}
+
+fn use_arrays() -> f64 {
+    let grads = unsafe { d_shift([1.0; DIM], [0.0; DIM], 2.0, 1.0) };
+    let x0: [f64; DIM] = grads.x0;
+    let primary_ret: [f64; DIM] = grads.primary_ret;
+    let (mut out, mut d_out) = (0.0, 1.0);
+    let d_pos: [f64; 3] = unsafe { d_energy([1.0; 3], [1.0; 3], &mut out, &mut d_out) };
+    let mut sum = grads + grads * 2.0;
+    sum += grads;
+    x0[0] + primary_ret[0] + grads.x1 + d_pos[0] + sum.x0[0]
+}
+
+// Settings for the backend, written into the metadata of this derivative only.
+#[differentiate_ext(
+    d_safe_log,
+    Reverse,
+    All(Active),
+    Active,
+    false,
+    options(strong_zero, runtime_activity = false)
+)]
+#[differentiate_ext(
+    d_fast_log,
+    Reverse,
+    All(Active),
+    Active,
+    false,
+    options(inline_primal, free_memory = false, loose_types)
+)]
+fn safe_log(x: f64) -> f64 {
+    x.max(0.0).ln()
+}
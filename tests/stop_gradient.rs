@@ -0,0 +1,24 @@
+#![allow(unused)]
+
+use autodiff::differentiate_ext;
+use autodiff_runtime::stop_gradient;
+
+// The norm is treated as a constant, so only the numerator is differentiated.
+#[differentiate_ext(d_normalize, Reverse, PerInput(Duplicated), Active, false)]
+fn normalize(x: &[f64; 3]) -> f64 {
+    let norm = stop_gradient(x.iter().map(|x| x * x).sum::<f64>().sqrt());
+    x[0] / norm
+}
+
+#[differentiate_ext(d_scaled, Forward(1), All(Duplicated), Gradient)]
+fn scaled(x: [f32; 2]) -> f32 {
+    let [a, b] = autodiff_runtime::stop_gradient(x);
+    a * b + x[0]
+}
+
+// Tuples and integers are passed through element-wise.
+#[differentiate_ext(d_weighted, Reverse, All(Active), Active, false)]
+fn weighted(x: f64, w: f64) -> f64 {
+    let (w, n) = stop_gradient((w, 2_u32));
+    x * w * n as f64
+}
@@ -0,0 +1,37 @@
+#![allow(unused)]
+
+use autodiff::{differentiate_ext, trajectory, Differentiable};
+
+#[derive(Clone, Differentiable)]
+#[repr(C)]
+pub struct State {
+    pub x: [f64; 2],
+    pub v: [f64; 2],
+}
+
+// Generates:
+// unsafe fn step_trajectory_adjoint(state0: &State, d_state: &mut StateTangent,
+//                                   n_steps: usize, snapshots: usize, dt: f64);
+#[trajectory(d_step)]
+#[differentiate_ext(d_step, Reverse, PerInput(Duplicated, Constant), None, false, tangent = [State])]
+pub fn step(state: &mut State, dt: f64) {
+    for i in 0..2 {
+        state.v[i] -= dt * state.x[i];
+        state.x[i] += dt * state.v[i];
+    }
+}
+
+#[trajectory(d_decay)]
+#[differentiate_ext(d_decay, Reverse, Auto(rate = Constant), None, false)]
+fn decay(u: &mut [f64; 4], rate: f64) {
+    u.iter_mut().for_each(|u| *u *= 1.0 - rate);
+}
+
+fn final_position_gradient(state0: &State) -> StateTangent {
+    let mut d_state = StateTangent {
+        x: [1.0, 0.0],
+        v: [0.0; 2],
+    };
+    unsafe { step_trajectory_adjoint(state0, &mut d_state, 10_000, 20, 1e-3) };
+    d_state
+}
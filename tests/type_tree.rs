@@ -0,0 +1,55 @@
+#![allow(unused, clippy::ptr_arg)]
+
+use autodiff::{differentiate_ext, HasTypeTree};
+
+#[derive(HasTypeTree)]
+#[repr(C)]
+pub struct Spring {
+    pub anchor: [f64; 2],
+    pub stiffness: f64,
+    pub id: u32,
+}
+
+// Generates next to the declaration a static `__enzyme_typetree_d_energy` with the bytes of
+// `ENERGY_TREE`, built from `__enzyme_typetree_text_d_energy` while compiling.
+#[differentiate_ext(
+    d_energy,
+    Reverse,
+    PerInput(Constant, Duplicated),
+    Active,
+    false,
+    type_tree
+)]
+fn energy(spring: &Spring, pos: &[f64; 2]) -> f64 {
+    let dx = pos[0] - spring.anchor[0];
+    let dy = pos[1] - spring.anchor[1];
+    0.5 * spring.stiffness * (dx * dx + dy * dy)
+}
+
+const ENERGY_TREE: &[u8] = b"0={[0]:Pointer, [0,0]:Float@double, [0,8]:Float@double, [0,16]:Float@double, [0,24]:Integer}\n\
+    1={[0]:Pointer, [0,-1]:Float@double}\n\
+    ret={[-1]:Float@double}\n\0";
+
+const fn bytes_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut i = 0;
+    while i < a.len() {
+        if a[i] != b[i] {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+const _: () = assert!(bytes_eq(
+    &__enzyme_typetree_text_d_energy.bytes::<{ __enzyme_typetree_text_d_energy.len() }>(),
+    ENERGY_TREE
+));
+
+#[differentiate_ext(d_norm, Forward(1), PerInput(Duplicated), Gradient, type_tree)]
+fn norm(x: &Vec<f32>) -> f32 {
+    x.iter().map(|x| x * x).sum::<f32>().sqrt()
+}